@@ -0,0 +1,127 @@
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use enigma::bombe::Bombe;
+use enigma::catalogue::Catalogue;
+use enigma::loader;
+use enigma::machine::Machine;
+use enigma::menu::Menu;
+use enigma::ngram::{self, NgramModel};
+use enigma::rotor;
+use enigma::search::Search;
+use enigma::solver;
+
+// Each benchmark runs for at least this long and reports the fastest run
+const MIN_TIME: Duration = Duration::from_secs(2);
+const MIN_RUNS: usize = 3;
+
+const PLAINTEXT: &str = "WETTERBERICHTFUERDIEBISKAYAUNDDENKANALHEUTEFRUEHBEWOELKTSPAETERAUFKLARENDWINDAUSNORDWESTMITSTAERKEVIERBISFUENFINBOEENSECHSSICHTGUTZEITWEISEMAESSIGBEIREGENSCHAUERN";
+const PLUGS: [(char, char); 10] = [('A', 'V'), ('B', 'S'), ('C', 'G'), ('D', 'L'), ('F', 'U'), ('H', 'Z'), ('I', 'N'), ('K', 'M'), ('O', 'W'), ('R', 'X')];
+
+fn main() {
+  // Run the benchmarks whose name contains one of the arguments, or all of them
+  // e.g. `cargo bench -- encipher bombe`
+  let filters: Vec<String> = env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+  let selected = |name: &str| filters.is_empty() || filters.iter().any(|filter| name.contains(filter.as_str()));
+
+  let catalogue = Catalogue::from_yaml(&loader::permutations_yaml(&[]).unwrap());
+  let order = |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+  let model = NgramModel::from_corpus(include_str!("../assets/german.txt"), 3);
+
+  let text = PLAINTEXT.repeat(1_000);
+  let letters = rotor::letters(&text);
+
+  if selected("encipher_str") {
+    let mut machine = machine(&catalogue, &order(&["II", "IV", "V"]), "B", &PLUGS);
+    bench("encipher_str", letters.len(), "letter", || {
+      machine.set_positions(&[1, 2, 3]);
+      machine.encipher_str(&text).unwrap()
+    });
+  }
+
+  if selected("encipher_letters") {
+    let mut machine = machine(&catalogue, &order(&["II", "IV", "V"]), "B", &PLUGS);
+    bench("encipher_letters", letters.len(), "letter", || {
+      machine.set_positions(&[1, 2, 3]);
+      machine.encipher_letters(&letters)
+    });
+  }
+
+  if selected("encipher_m4") {
+    let mut machine = machine(&catalogue, &order(&["Beta", "II", "IV", "I"]), "B-Thin", &PLUGS);
+    bench("encipher_m4", letters.len(), "letter", || {
+      machine.set_positions(&[1, 2, 3, 4]);
+      machine.encipher_letters(&letters)
+    });
+  }
+
+  if selected("signal_with") {
+    let machine = machine(&catalogue, &order(&["II", "IV", "V"]), "B", &PLUGS);
+    bench("signal_with", text.len(), "letter", || {
+      let mut stages = 0;
+      for c in text.chars() {
+        machine.signal_with(c, |_, _| stages += 1).unwrap();
+      }
+      stages
+    });
+  }
+
+  // A short message, as the solver and bombe see them
+  let mut sender = machine(&catalogue, &order(&["II", "IV", "V"]), "B", &PLUGS);
+  sender.set_positions(&[10, 3, 20]);
+  let ciphertext = sender.encipher_str(&PLAINTEXT[..150]).unwrap();
+
+  if selected("ioc_search") {
+    let mut search = Search::new(&catalogue, vec![order(&["II", "IV", "V"])], "B", &[]);
+    search.set_threads(1);
+    bench("ioc_search", search.size(), "position", || {
+      search.best(&ciphertext, 5, ngram::index_of_coincidence_letters, |_, _| {}).unwrap()
+    });
+  }
+
+  if selected("climb_plugboard") {
+    let mut sender = machine(&catalogue, &order(&["II", "IV", "V"]), "B", &PLUGS[..6]);
+    let ciphertext = sender.encipher_str(&PLAINTEXT[..150]).unwrap();
+    let mut machine = machine(&catalogue, &order(&["II", "IV", "V"]), "B", &[]);
+    bench("climb_plugboard", 1, "climb", || {
+      solver::climb_plugboard(&mut machine, &ciphertext, &[0, 0, 0], &model).unwrap()
+    });
+  }
+
+  if selected("bombe") {
+    let menu = Menu::new(&PLAINTEXT[..24], &ciphertext, 0).unwrap();
+    let mut search = Search::new(&catalogue, vec![order(&["II", "IV", "V"])], "B", &[]);
+    search.set_threads(1);
+    bench("bombe", search.size(), "position", || {
+      search.run(|machine| Bombe::new(&menu, machine), |bombe, machine, positions| bombe.test_at(machine, positions), |_, _| {}).unwrap()
+    });
+  }
+}
+
+fn machine(catalogue: &Catalogue, wheel_order: &[String], reflector: &str, plugs: &[(char, char)]) -> Machine {
+  // A machine with WHEEL_ORDER, rings at 01 and PLUGS
+  let search = Search::new(catalogue, Vec::new(), reflector, &[]);
+  let mut machine = search.machine(wheel_order).unwrap();
+  for (c1, c2) in plugs {
+    machine.plugboard_mut().add_connection(*c1, *c2).unwrap();
+  }
+  machine
+}
+
+fn bench<T, F: FnMut() -> T>(name: &str, units: usize, unit: &str, mut run: F) {
+  // Time RUN until MIN_TIME has passed and report its fastest run, in all and per UNIT
+  let mut fastest = Duration::MAX;
+  let mut runs = 0;
+  let started = Instant::now();
+
+  while runs < MIN_RUNS || started.elapsed() < MIN_TIME {
+    let start = Instant::now();
+    black_box(run());
+    fastest = fastest.min(start.elapsed());
+    runs += 1;
+  }
+
+  let per_unit = fastest.as_nanos() as f64 / units.max(1) as f64;
+  println!("{:<18} {:>12.3?} per run, {:>12.1} ns per {} ({} runs)", name, fastest, per_unit, unit, runs);
+}
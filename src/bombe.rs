@@ -0,0 +1,283 @@
+use crate::error::EnigmaError;
+use crate::machine::Machine;
+use crate::menu::Menu;
+use crate::rotor::Rotor;
+use crate::ALPHABET;
+
+const LETTERS: usize = ALPHABET.len();
+
+// Live wires of the bombe, one register of 26 wires for every letter
+type Registers = [[bool; LETTERS]; LETTERS];
+
+// Rotor positions where the menu holds together, with the plug pairs it implies
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stop {
+  wheel_order: Vec<String>,
+  positions: Vec<usize>,
+  plugs: Vec<(char, char)>,
+}
+
+impl Stop {
+
+  pub fn wheel_order(&self) -> &[String] {
+    &self.wheel_order
+  }
+
+  pub fn positions(&self) -> &[usize] {
+    // Rotor positions at the start of the message, with the rings the bombe was run with
+    &self.positions
+  }
+
+  pub fn window(&self) -> String {
+    // Letters shown in the rotor windows at the start of the message
+    self.positions.iter().map(|position| ALPHABET[*position]).collect()
+  }
+
+  pub fn plugs(&self) -> &[(char, char)] {
+    // Plug pairs deduced from the menu, letters that are not in it stay unknown
+    &self.plugs
+  }
+}
+
+// Wiring of a wheel in the forward and reverse direction, by letter index
+type Wiring = ([usize; LETTERS], [usize; LETTERS]);
+
+// Turing-Welchman bombe, a scrambler for every link of the menu joined through a diagonal board
+pub struct Bombe<'a> {
+  menu: &'a Menu,
+  wheel_order: Vec<String>,
+  connections: Vec<Vec<(usize, usize)>>,
+  scramblers: Vec<[usize; LETTERS]>,
+  fast_drum: Wiring,
+  inner: Vec<[usize; LETTERS]>,
+}
+
+impl<'a> Bombe<'a> {
+
+  pub fn new(menu: &'a Menu, machine: &Machine) -> Result<Self, EnigmaError> {
+    // Set the bombe up with drums wired as the machine's rotors and reflector
+    // The plugboard is left out and the rings stay as set on the machine, 01 like the bombe's drums by default
+
+    // For every letter the scramblers it is wired to, with the letter at their other end
+    let connections = (0..LETTERS)
+      .map(|letter| menu.links_at(letter).map(|(i, link)| (i, link.other(letter))).collect())
+      .collect();
+
+    // Read the wiring once, the scramblers are worked out from it at every position
+    // Everything left of the fast drum only changes on a turnover, so is worked out ahead for each position
+    let mut drums: Vec<Wiring> = machine.rotors().iter().map(wiring).collect();
+    let reflector = match machine.reflector() {
+      Some(reflector) => wiring(reflector).0,
+      None => std::array::from_fn(|letter| letter),
+    };
+    let fast_drum = drums.pop().unwrap_or(([0; LETTERS], [0; LETTERS]));
+    let inner = (0..LETTERS.pow(drums.len() as u32))
+      .map(|n| {
+        let shifts: Vec<usize> = (0..drums.len()).map(|i| n / LETTERS.pow((drums.len() - 1 - i) as u32) % LETTERS).collect();
+        std::array::from_fn(|letter| scramble(&drums, &reflector, &shifts, letter))
+      })
+      .collect();
+
+    Ok(Bombe {
+      menu,
+      wheel_order: machine.rotors().iter().map(|rotor| rotor.get_name().to_string()).collect(),
+      connections,
+      scramblers: vec![[0; LETTERS]; menu.links().len()],
+      fast_drum,
+      inner,
+    })
+  }
+
+  pub fn test_at(&mut self, machine: &mut Machine, positions: &[usize]) -> Result<Option<Stop>, EnigmaError> {
+    // Set the drums to POSITIONS and report a stop if the menu holds together there
+    machine.set_positions(positions);
+    self.set_scramblers(machine)?;
+    Ok(self.test().map(|plugs| Stop {
+      wheel_order: self.wheel_order.clone(),
+      positions: positions.to_vec(),
+      plugs,
+    }))
+  }
+
+  fn set_scramblers(&mut self, machine: &mut Machine) -> Result<(), EnigmaError> {
+    // Set each link's scrambler to the machine as it enciphers that letter of the message
+    let last = self.menu.links().iter().map(|link| link.position()).max().unwrap_or_default();
+
+    for position in 0..=last {
+      machine.advance();
+      let shifts = machine.rotors().iter().map(|rotor| (rotor.get_offset() + LETTERS - rotor.get_ring()) % LETTERS);
+      let (inner, shift) = shifts.fold((0, 0), |(inner, shift), next| (inner * LETTERS + shift, next));
+      let inner = &self.inner[inner];
+
+      for (link, scrambler) in self.menu.links().iter().zip(self.scramblers.iter_mut()) {
+        if link.position() == position {
+          for (letter, wire) in scrambler.iter_mut().enumerate() {
+            *wire = through(inner[through(letter, &self.fast_drum.0, shift)], &self.fast_drum.1, shift);
+          }
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  fn test(&self) -> Option<Vec<(char, char)>> {
+    // Energise one wire of the test register and see how far the current spreads
+    // With all 26 wires live no plug for the test letter fits and the bombe runs on
+    // Otherwise each wire left dead is tried on its own, a stop needs every register to end
+    // up with at most one live wire (one plug per letter)
+    let test_letter = self.menu.test_letter();
+    let registers = self.energise(test_letter, 0, false)?;
+    if registers.iter().all(|register| register.iter().filter(|live| **live).count() <= 1) {
+      return Some(plugs(&registers));
+    }
+
+    (1..LETTERS)
+      .filter(|wire| !registers[test_letter][*wire])
+      .find_map(|wire| self.energise(test_letter, wire, true))
+      .map(|registers| plugs(&registers))
+  }
+
+  fn energise(&self, letter: usize, wire: usize, single: bool) -> Option<Registers> {
+    // Spread the current from one wire through the scramblers and the diagonal board
+    // Gives up once every wire of the test register is live, or with SINGLE once any
+    // register has a second live wire
+    let test_letter = letter;
+    let mut registers: Registers = [[false; LETTERS]; LETTERS];
+    let mut live = [0; LETTERS];
+    let mut pending = vec![(letter, wire)];
+
+    while let Some((letter, wire)) = pending.pop() {
+      if registers[letter][wire] {
+        continue;
+      }
+      registers[letter][wire] = true;
+      live[letter] += 1;
+      if (single && live[letter] > 1) || live[test_letter] == LETTERS {
+        return None;
+      }
+
+      // The diagonal board => plugging LETTER to WIRE also plugs WIRE to LETTER
+      pending.push((wire, letter));
+      for (i, other) in &self.connections[letter] {
+        pending.push((*other, self.scramblers[*i][wire]));
+      }
+    }
+
+    Some(registers)
+  }
+}
+
+fn wiring(rotor: &Rotor) -> Wiring {
+  // Read the wiring of ROTOR as it would be at position A with the ring at 01
+  let shift = (rotor.get_offset() + LETTERS - rotor.get_ring()) % LETTERS;
+  let mut forward = [0; LETTERS];
+  let mut reverse = [0; LETTERS];
+
+  for (letter, wire) in forward.iter_mut().enumerate() {
+    let index = rotor.forward_index(((letter + LETTERS - shift) % LETTERS) as u8);
+    *wire = (index as usize + shift) % LETTERS;
+    reverse[*wire] = letter;
+  }
+
+  (forward, reverse)
+}
+
+fn through(letter: usize, wiring: &[usize; LETTERS], shift: usize) -> usize {
+  // Pass LETTER through one way of a wheel turned SHIFT places
+  (wiring[(letter + shift) % LETTERS] + LETTERS - shift) % LETTERS
+}
+
+fn scramble(drums: &[Wiring], reflector: &[usize; LETTERS], shifts: &[usize], mut letter: usize) -> usize {
+  // Pass LETTER through the drums at SHIFTS, the reflector and back, as the machine does without plugs
+  for (drum, shift) in drums.iter().zip(shifts).rev() {
+    letter = through(letter, &drum.0, *shift);
+  }
+  letter = reflector[letter];
+  for (drum, shift) in drums.iter().zip(shifts) {
+    letter = through(letter, &drum.1, *shift);
+  }
+  letter
+}
+
+fn plugs(registers: &Registers) -> Vec<(char, char)> {
+  // Read the plug pairs off the live wires, leaving out letters plugged to themselves
+  let mut plugs = Vec::new();
+  for (letter, register) in registers.iter().enumerate() {
+    for wire in (letter + 1)..LETTERS {
+      if register[wire] {
+        plugs.push((ALPHABET[letter], ALPHABET[wire]));
+      }
+    }
+  }
+  plugs
+}
+
+pub fn wheel_orders(rotors: &[String], length: usize) -> Vec<Vec<String>> {
+  // Every ordered choice of LENGTH different rotors
+  if length == 0 {
+    return vec![Vec::new()];
+  }
+
+  let mut orders = Vec::new();
+  for (i, rotor) in rotors.iter().enumerate() {
+    let rest: Vec<String> = rotors.iter().enumerate().filter(|(j, _)| *j != i).map(|(_, name)| name.clone()).collect();
+    for mut order in wheel_orders(&rest, length - 1) {
+      order.insert(0, rotor.clone());
+      orders.push(order);
+    }
+  }
+  orders
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::catalogue::Catalogue;
+  use crate::loader;
+  use crate::search::Search;
+
+  fn order() -> Vec<String> {
+    vec!["I".to_string(), "II".to_string(), "III".to_string()]
+  }
+
+  fn machine(catalogue: &Catalogue, plugs: &[(char, char)]) -> Machine {
+    let mut machine = Search::new(catalogue, Vec::new(), "B", &[]).machine(&order()).unwrap();
+    for (c1, c2) in plugs {
+      machine.plugboard_mut().add_connection(*c1, *c2).unwrap();
+    }
+    machine
+  }
+
+  #[test]
+  fn test_bombe_finds_key() {
+    let catalogue = Catalogue::from_yaml(&loader::permutations_yaml(&[]).unwrap());
+    let plugs = [('A', 'V'), ('B', 'S'), ('C', 'G'), ('D', 'L'), ('F', 'U'), ('H', 'Z')];
+    let mut sender = machine(&catalogue, &plugs);
+    sender.set_positions(&[10, 3, 20]);
+    let plaintext = "WETTERVORHERSAGEBISKAYAXHEUTEKLAR";
+    let ciphertext = sender.encipher_str(plaintext).unwrap();
+
+    let menu = Menu::new(&plaintext[..24], &ciphertext, 0).unwrap();
+    let search = Search::new(&catalogue, vec![order()], "B", &[]);
+    let stops = search.run(
+      |machine| Bombe::new(&menu, machine),
+      |bombe, machine, positions| bombe.test_at(machine, positions),
+      |_, _| {},
+    ).unwrap();
+
+    let stop = stops.iter().find(|stop| stop.window() == "KDU").expect("no stop at the key");
+    for (c1, c2) in stop.plugs() {
+      assert!(plugs.contains(&(*c1, *c2)) || plugs.contains(&(*c2, *c1)), "wrong plug {}{}", c1, c2);
+    }
+    assert!(stops.len() < 20);
+  }
+
+  #[test]
+  fn test_wheel_orders() {
+    let rotors: Vec<String> = ["I", "II", "III", "IV", "V"].iter().map(|name| name.to_string()).collect();
+    let orders = wheel_orders(&rotors, 3);
+    assert_eq!(orders.len(), 60);
+    assert_eq!(orders[1], vec!["I", "II", "IV"]);
+  }
+}
@@ -0,0 +1,227 @@
+use yaml_rust::Yaml;
+
+use crate::error::EnigmaError;
+use crate::rotor::Rotor;
+use crate::validation::ValidationError;
+
+#[derive(Debug, Clone)]
+pub struct WheelSpec {
+  name: String,
+  wiring: String,
+  notches: Vec<char>,
+  thin: bool,
+}
+
+#[derive(Debug)]
+pub struct Catalogue {
+  rotors: Vec<WheelSpec>,
+  reflectors: Vec<WheelSpec>,
+}
+
+impl Catalogue {
+
+  pub fn from_yaml(yaml: &Yaml) -> Self {
+    // Build the catalogue from a validated permutations file
+    Catalogue {
+      rotors: Self::wheel_specs(&yaml["rotors"]),
+      reflectors: Self::wheel_specs(&yaml["reflectors"]),
+    }
+  }
+
+  fn wheel_specs(section: &Yaml) -> Vec<WheelSpec> {
+    // Read every named wheel in a catalogue section
+    section
+      .as_hash()
+      .map(|wheels| wheels
+        .iter()
+        .filter_map(|(name, wheel)| Some(WheelSpec {
+          name: Self::yaml_name(name)?,
+          wiring: wheel["wiring"].as_str()?.to_string(),
+          notches: wheel["notches"].as_str().unwrap_or("").chars().collect(),
+          thin: wheel["thin"].as_bool().unwrap_or(false),
+        }))
+        .collect())
+      .unwrap_or_default()
+  }
+
+  fn yaml_name(name: &Yaml) -> Option<String> {
+    // Catalogue keys such as `I` are strings but `4` would be parsed as an integer
+    match name {
+      Yaml::String(s) => Some(s.clone()),
+      Yaml::Integer(i) => Some(i.to_string()),
+      _ => None,
+    }
+  }
+
+  pub fn rotor_names(&self) -> Vec<&str> {
+    // Return the names of all rotors in the catalogue
+    self.rotors.iter().map(|spec| spec.name.as_str()).collect()
+  }
+
+  pub fn greek_wheel_names(&self) -> Vec<&str> {
+    // Return the names of the thin Greek wheels that only fit the M4
+    self.rotors.iter().filter(|spec| spec.thin).map(|spec| spec.name.as_str()).collect()
+  }
+
+  pub fn rotor_range(&self, value: &str) -> Result<Vec<String>, ValidationError> {
+    // Select rotors by a catalogue range such as "I-V" or a list such as "I,II,IV"
+    // Greek wheels are left out, they only ever take the first M4 slot
+    let names: Vec<String> = match value.split_once('-') {
+      Some((first, last)) => {
+        let position = |name: &str| self.rotors
+          .iter()
+          .position(|spec| spec.name.eq_ignore_ascii_case(name.trim()))
+          .ok_or_else(|| ValidationError::UnknownRotor(name.trim().to_string()));
+        let (first, last) = (position(first)?, position(last)?);
+        self.rotors.get(first..=last).unwrap_or_default().iter().map(|spec| spec.name.clone()).collect()
+      }
+      None => parse_wheel_order(value),
+    };
+
+    names
+      .into_iter()
+      .filter(|name| Self::find(&self.rotors, name).is_none_or(|spec| !spec.thin))
+      .map(|name| Self::find(&self.rotors, &name)
+        .map(|spec| spec.name.clone())
+        .ok_or(ValidationError::UnknownRotor(name)))
+      .collect()
+  }
+
+  pub fn reflector_names(&self) -> Vec<&str> {
+    // Return the names of all reflectors in the catalogue
+    self.reflectors.iter().map(|spec| spec.name.as_str()).collect()
+  }
+
+  pub fn rotor(&self, name: &str) -> Result<Rotor, EnigmaError> {
+    // Create the named rotor
+    let spec = Self::find(&self.rotors, name)
+      .ok_or_else(|| ValidationError::UnknownRotor(name.to_string()))?;
+
+    Rotor::new(&spec.name, &spec.wiring, Some(spec.notches.clone()))
+  }
+
+  pub fn reflector(&self, name: &str, m4: bool) -> Result<Rotor, EnigmaError> {
+    // Create the named reflector, "UKW-B" and "B" are both accepted
+    // The M4 only takes thin reflectors, the three rotor machine only thick ones
+    let short_name = name
+      .strip_prefix("UKW-")
+      .or_else(|| name.strip_prefix("ukw-"))
+      .unwrap_or(name);
+
+    let spec = Self::find(&self.reflectors, short_name)
+      .ok_or_else(|| ValidationError::UnknownReflector(name.to_string()))?;
+
+    if spec.thin != m4 {
+      return Err(ValidationError::IncompatibleReflector(spec.name.clone()).into());
+    }
+
+    Rotor::new(&spec.name, &spec.wiring, None)
+  }
+
+  pub fn wheel_order(&self, names: &[String]) -> Result<Vec<Rotor>, EnigmaError> {
+    // Create the rotors for a wheel order (slowest first), each rotor can only be used once
+    // Three rotors for the standard machine, or a thin Greek wheel plus three for the M4
+    let invalid = || ValidationError::InvalidWheelOrder(names.join(","));
+    let mut rotors: Vec<Rotor> = Vec::new();
+
+    if names.len() != 3 && names.len() != 4 {
+      return Err(invalid().into());
+    }
+
+    for (i, name) in names.iter().enumerate() {
+      let greek_slot = names.len() == 4 && i == 0;
+      if Self::find(&self.rotors, name).is_some_and(|spec| spec.thin != greek_slot) {
+        return Err(invalid().into());
+      }
+
+      let rotor = self.rotor(name)?;
+      if rotors.iter().any(|r| r.get_name() == rotor.get_name()) {
+        return Err(ValidationError::DuplicateRotor(rotor.get_name().to_string()).into());
+      }
+      rotors.push(rotor);
+    }
+
+    Ok(rotors)
+  }
+
+  fn find<'a>(specs: &'a [WheelSpec], name: &str) -> Option<&'a WheelSpec> {
+    // Find a wheel by name, ignoring case
+    specs.iter().find(|spec| spec.name.eq_ignore_ascii_case(name))
+  }
+}
+
+pub fn parse_wheel_order(value: &str) -> Vec<String> {
+  // Split a wheel order such as "II,IV,I" or "II IV I" into rotor names
+  value
+    .split(|c: char| c.is_whitespace() || c == ',')
+    .filter(|s| !s.is_empty())
+    .map(String::from)
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use yaml_rust::YamlLoader;
+
+  fn catalogue() -> Catalogue {
+    let yaml = YamlLoader::load_from_str("
+rotors:
+  I:   { wiring: EKMFLGDQVZNTOWYHXUSPAIBRCJ, notches: Q }
+  II:  { wiring: AJDKSIRUXBLHWTMCQGZNPYFVOE, notches: E }
+  III: { wiring: BDFHJLCPRTXVZNYEIWGAKMUSQO, notches: V }
+  VI:  { wiring: JPGVOUMFYQBENHZRDKASXLICTW, notches: ZM }
+  Beta: { wiring: LEYJVCNIXWPBQMDRTAKZGFUHOS, thin: true }
+reflectors:
+  B:   { wiring: YRUHQSLDPXNGOKMIEBFZCWVJAT }
+  B-Thin: { wiring: ENKQAUYWJICOPBLMDXZVFTHRGS, thin: true }
+").unwrap();
+    Catalogue::from_yaml(&yaml[0])
+  }
+
+  #[test]
+  fn test_wheel_order() {
+    let catalogue = catalogue();
+    let names = parse_wheel_order("VI, i III");
+    let rotors = catalogue.wheel_order(&names).unwrap();
+
+    assert_eq!(rotors[0].get_name(), "VI");
+    assert_eq!(rotors[1].get_name(), "I");
+    assert_eq!(rotors[0].forward_permutation('A').unwrap(), 'J');
+
+    let names = parse_wheel_order("Beta,VI,I,III");
+    assert_eq!(catalogue.wheel_order(&names).unwrap().len(), 4);
+  }
+
+  #[test]
+  fn test_invalid_wheel_order() {
+    let catalogue = catalogue();
+    assert!(catalogue.wheel_order(&parse_wheel_order("I,I,II")).is_err());
+    assert!(catalogue.wheel_order(&parse_wheel_order("I,IX,II")).is_err());
+    assert!(catalogue.wheel_order(&parse_wheel_order("I,II")).is_err());
+    assert!(catalogue.wheel_order(&parse_wheel_order("Beta,I,II")).is_err());
+    assert!(catalogue.wheel_order(&parse_wheel_order("I,Beta,II,III")).is_err());
+  }
+
+  #[test]
+  fn test_rotor_range() {
+    let catalogue = catalogue();
+    assert_eq!(catalogue.rotor_range("I-VI").unwrap(), ["I", "II", "III", "VI"]);
+    assert_eq!(catalogue.rotor_range("ii-beta").unwrap(), ["II", "III", "VI"]);
+    assert_eq!(catalogue.rotor_range("VI,i").unwrap(), ["VI", "I"]);
+    assert!(catalogue.rotor_range("I-IX").is_err());
+    assert!(catalogue.rotor_range("I,IX").is_err());
+    assert_eq!(catalogue.greek_wheel_names(), ["Beta"]);
+  }
+
+  #[test]
+  fn test_reflector_names() {
+    let catalogue = catalogue();
+    assert_eq!(catalogue.reflector("UKW-B", false).unwrap().get_name(), "B");
+    assert_eq!(catalogue.reflector("b", false).unwrap().get_name(), "B");
+    assert_eq!(catalogue.reflector("B-Thin", true).unwrap().get_name(), "B-Thin");
+    assert!(catalogue.reflector("C", false).is_err());
+    assert!(catalogue.reflector("B", true).is_err());
+    assert!(catalogue.reflector("B-Thin", false).is_err());
+  }
+}
@@ -1,4 +1,5 @@
-use clap::Parser;
+use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(
@@ -9,7 +10,7 @@ use clap::Parser;
 )]
 pub struct Cli {
     /// Run in debug mode => Supress ASCII and print received signals and internal permutations.
-    #[arg(short = 'd', long = "debug")]
+    #[arg(short = 'd', long = "debug", global = true)]
     pub debug_mode: bool,
 
     /// Run in secret mode => The message will not be displayed to the screen.
@@ -23,4 +24,162 @@ pub struct Cli {
     /// Run with animations => Activate animations.
     #[arg(short = 'a', long = "animate")]
     pub animate: bool,
-}
\ No newline at end of file
+
+    /// Run as a four rotor Kriegsmarine M4 => Greek wheel and thin reflector.
+    #[arg(short = 'm', long = "m4", global = true)]
+    pub m4: bool,
+
+    /// Wheel order from the rotor catalogue, slowest rotor first => e.g. "II,IV,I".
+    #[arg(short = 'w', long = "rotors", global = true)]
+    pub rotors: Option<String>,
+
+    /// Reflector from the catalogue => e.g. "B" or "UKW-C".
+    #[arg(short = 'u', long = "reflector", global = true)]
+    pub reflector: Option<String>,
+
+    /// Ring settings (Ringstellung), slowest rotor first => e.g. "AAA" or "01 01 01".
+    #[arg(short = 'r', long = "rings", global = true)]
+    pub rings: Option<String>,
+
+    /// Initial rotor positions (Grundstellung), slowest rotor first => e.g. "QEV" or "17 05 22".
+    #[arg(short = 'p', long = "positions", global = true)]
+    pub positions: Option<String>,
+
+    /// Plugboard connections (Steckerverbindungen) => e.g. "AV BS CG DL".
+    #[arg(long = "plugs", global = true)]
+    pub plugs: Option<String>,
+
+    /// Monthly key sheet file => Sets the wheel order, rings and plugs from the day selected with --day.
+    #[arg(long = "key-sheet", global = true, requires = "day", conflicts_with_all = ["m4", "rotors", "rings", "plugs"])]
+    pub key_sheet: Option<PathBuf>,
+
+    /// Day of the month to take from the key sheet => e.g. 14.
+    #[arg(long = "day", global = true, requires = "key_sheet", value_parser = clap::value_parser!(u32).range(1..=31))]
+    pub day: Option<u32>,
+
+    /// Message key indicator procedure => The message key is enciphered at the ground setting (--positions).
+    #[arg(long = "indicator", global = true, value_enum)]
+    pub indicator: Option<IndicatorProcedure>,
+
+    /// Message key to send with --indicator, one letter per rotor => Chosen at random when not given.
+    #[arg(long = "message-key", global = true, requires = "indicator")]
+    pub message_key: Option<String>,
+
+    /// Receive a message => With --indicator the first letters typed or read are the enciphered message key.
+    #[arg(long = "receive", global = true, conflicts_with = "message_key")]
+    pub receive: bool,
+
+    /// Write (or with --receive, read) headless messages in radio format => Header, Kenngruppe and five letter groups.
+    #[arg(long = "radio", global = true)]
+    pub radio: bool,
+
+    /// File each message is saved to with ENTER => Defaults to print/msg.txt.
+    #[arg(long = "out", global = true)]
+    pub out: Option<PathBuf>,
+
+    /// Also append every saved message to this log, with a timestamp and the machine settings.
+    #[arg(long = "log", global = true)]
+    pub log: Option<PathBuf>,
+
+    /// Add the transcript of typed keys and lamp letters to each saved message.
+    #[arg(long = "transcript", global = true)]
+    pub transcript: bool,
+
+    /// Directory with permutations.yaml and/or assets/ascii*.txt overriding the built-in files.
+    /// Also read from $ENIGMA_CONFIG and $XDG_CONFIG_HOME/enigma.
+    #[arg(long = "config", global = true)]
+    pub config_dir: Option<PathBuf>,
+
+    /// Worker threads for the bombe and solver => Defaults to one per CPU core.
+    #[arg(long = "threads", global = true, value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Load the machine from a saved state file => Replaces the rotor, ring, position and plug options.
+    #[arg(long = "state", global = true, conflicts_with_all = ["m4", "rotors", "reflector", "rings", "positions", "plugs", "key_sheet"])]
+    pub state: Option<PathBuf>,
+
+    /// Save the machine state to this file on exit (and with CTRL+S while typing).
+    #[arg(long = "save-state", global = true)]
+    pub save_state: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Encipher stdin to stdout without the terminal interface => Deciphers with the same settings.
+    #[command(alias = "decrypt")]
+    Encrypt,
+
+    /// Generate a random monthly key sheet => Rotors are picked from --rotors, e.g. "I-V".
+    Keysheet {
+        /// Month of the key sheet => e.g. "2026-11".
+        #[arg(long = "month")]
+        month: String,
+
+        /// Seed for the random settings => The same seed always gives the same sheet.
+        #[arg(long = "seed")]
+        seed: Option<u64>,
+
+        /// Output format => The YAML sheet is read back with --key-sheet.
+        #[arg(long = "format", value_enum, default_value_t = SheetFormat::Yaml)]
+        format: SheetFormat,
+    },
+
+    /// Run the Turing-Welchman bombe against a ciphertext file => Tries every wheel order from --rotors (default "I-V").
+    Bombe {
+        /// Known plaintext (crib) => e.g. "WETTERBERICHT".
+        #[arg(long = "crib")]
+        crib: String,
+
+        /// Position of the crib in the ciphertext, counting letters from 0.
+        #[arg(long = "at", default_value_t = 0)]
+        at: usize,
+
+        /// File with the ciphertext => Letters A-Z, radio header lines (with '=') are skipped.
+        ciphertext: PathBuf,
+    },
+
+    /// List where a crib can sit in a ciphertext file => No letter may encipher to itself.
+    Cribs {
+        /// Known plaintext (crib) => e.g. "WETTERBERICHT".
+        #[arg(long = "crib")]
+        crib: String,
+
+        /// Count the loops in the menu at each offset and mark the offsets with the longest loop.
+        #[arg(long = "loops")]
+        loops: bool,
+
+        /// File with the ciphertext => Letters A-Z, radio header lines (with '=') are skipped.
+        ciphertext: PathBuf,
+    },
+
+    /// Recover the key from the ciphertext alone => Tries every wheel order from --rotors (default "I-V").
+    Solve {
+        /// Letters per n-gram when scoring plugboard pairs => 2 (bigrams), 3 (trigrams) or 4 (quadgrams).
+        #[arg(long = "ngram", default_value_t = 3, value_parser = clap::value_parser!(u8).range(2..=4))]
+        ngram: u8,
+
+        /// Start positions (best by index of coincidence) carried on to the ring and plugboard search.
+        #[arg(long = "keep", default_value_t = 5)]
+        keep: usize,
+
+        /// File with the ciphertext => Letters A-Z, radio header lines (with '=') are skipped.
+        ciphertext: PathBuf,
+    },
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum SheetFormat {
+    Yaml,
+    Table,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum IndicatorProcedure {
+    /// Message key enciphered twice, as before 1940.
+    Doubled,
+    /// Message key enciphered once, as from 1940.
+    Single,
+}
@@ -1,10 +1,33 @@
-use crate::cli::Cli;
+use std::path::{Path, PathBuf};
+
+use crate::cli::{Cli, Command, IndicatorProcedure};
 
 pub struct Config {
   debug_mode: bool,
   secret_mode: bool,
   show_instructions: bool,
   animate: bool,
+  m4: bool,
+  rotors: Option<String>,
+  reflector: Option<String>,
+  rings: Option<String>,
+  positions: Option<String>,
+  plugs: Option<String>,
+  key_sheet: Option<PathBuf>,
+  day: Option<u32>,
+  indicator: Option<IndicatorProcedure>,
+  message_key: Option<String>,
+  receive: bool,
+  radio: bool,
+  out: PathBuf,
+  log: Option<PathBuf>,
+  transcript: bool,
+  config_dir: Option<PathBuf>,
+  threads: Option<usize>,
+  state: Option<PathBuf>,
+  save_state: Option<PathBuf>,
+  headless: bool,
+  command: Option<Command>,
 }
 
 impl Config {
@@ -14,6 +37,27 @@ impl Config {
       secret_mode: cli.secret_mode,
       show_instructions: cli.show_instructions,
       animate: cli.animate,
+      m4: cli.m4,
+      rotors: cli.rotors.clone(),
+      reflector: cli.reflector.clone(),
+      rings: cli.rings.clone(),
+      positions: cli.positions.clone(),
+      plugs: cli.plugs.clone(),
+      key_sheet: cli.key_sheet.clone(),
+      day: cli.day,
+      indicator: cli.indicator,
+      message_key: cli.message_key.clone(),
+      receive: cli.receive,
+      radio: cli.radio,
+      out: cli.out.clone().unwrap_or_else(|| PathBuf::from("print/msg.txt")),
+      log: cli.log.clone(),
+      transcript: cli.transcript,
+      config_dir: cli.config_dir.clone(),
+      threads: cli.threads.map(|threads| threads as usize),
+      state: cli.state.clone(),
+      save_state: cli.save_state.clone(),
+      headless: matches!(cli.command, Some(Command::Encrypt)),
+      command: cli.command.clone(),
     }
   }
 
@@ -22,7 +66,15 @@ impl Config {
   }
 
   pub fn is_display(&self) -> bool {
-    !self.debug_mode
+    !self.debug_mode && !self.headless
+  }
+
+  pub fn is_headless(&self) -> bool {
+    self.headless
+  }
+
+  pub fn command(&self) -> Option<&Command> {
+    self.command.as_ref()
   }
 
   pub fn is_secret(&self) -> bool {
@@ -36,5 +88,81 @@ impl Config {
   pub fn animate(&self) -> bool {
     self.animate
   }
+
+  pub fn is_m4(&self) -> bool {
+    self.m4
+  }
+
+  pub fn rotors(&self) -> Option<&str> {
+    self.rotors.as_deref()
+  }
+
+  pub fn reflector(&self) -> Option<&str> {
+    self.reflector.as_deref()
+  }
+
+  pub fn rings(&self) -> Option<&str> {
+    self.rings.as_deref()
+  }
+
+  pub fn positions(&self) -> Option<&str> {
+    self.positions.as_deref()
+  }
+
+  pub fn plugs(&self) -> Option<&str> {
+    self.plugs.as_deref()
+  }
+
+  pub fn key_sheet(&self) -> Option<&Path> {
+    self.key_sheet.as_deref()
+  }
+
+  pub fn day(&self) -> Option<u32> {
+    self.day
+  }
+
+  pub fn indicator(&self) -> Option<IndicatorProcedure> {
+    self.indicator
+  }
+
+  pub fn message_key(&self) -> Option<&str> {
+    self.message_key.as_deref()
+  }
+
+  pub fn is_receiving(&self) -> bool {
+    self.receive
+  }
+
+  pub fn is_radio(&self) -> bool {
+    self.radio
+  }
+
+  pub fn out(&self) -> &Path {
+    &self.out
+  }
+
+  pub fn log(&self) -> Option<&Path> {
+    self.log.as_deref()
+  }
+
+  pub fn include_transcript(&self) -> bool {
+    self.transcript
+  }
+
+  pub fn config_dir(&self) -> Option<&Path> {
+    self.config_dir.as_deref()
+  }
+
+  pub fn threads(&self) -> Option<usize> {
+    self.threads
+  }
+
+  pub fn state(&self) -> Option<&Path> {
+    self.state.as_deref()
+  }
+
+  pub fn save_state(&self) -> Option<&Path> {
+    self.save_state.as_deref()
+  }
   
 }
\ No newline at end of file
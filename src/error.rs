@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use yaml_rust::ScanError;
+
+use crate::validation::ValidationError;
+
+// Crate-wide error type, extending validation errors with failures while running the machine
+#[derive(Debug)]
+pub enum EnigmaError {
+    Validation(ValidationError),
+    InvalidCharacter(char),
+    AlreadyPlugged(char),
+    InvalidIndicator(String),
+    Yaml(ScanError),
+    Io(io::Error),
+}
+
+impl fmt::Display for EnigmaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EnigmaError::Validation(err) => write!(f, "{}", err),
+            EnigmaError::InvalidCharacter(c) => write!(f, "Invalid character: {:?} - the machine only has keys A-Z", c),
+            EnigmaError::AlreadyPlugged(c) => write!(f, "Plugboard socket {} is already in use", c),
+            EnigmaError::InvalidIndicator(indicator) => {
+                write!(f, "Invalid indicator: '{}' - does not decipher to a message key at the ground setting", indicator)
+            }
+            EnigmaError::Yaml(err) => write!(f, "Invalid YAML: {}", err),
+            EnigmaError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for EnigmaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EnigmaError::Validation(err) => Some(err),
+            EnigmaError::Yaml(err) => Some(err),
+            EnigmaError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ValidationError> for EnigmaError {
+    fn from(err: ValidationError) -> Self {
+        EnigmaError::Validation(err)
+    }
+}
+
+impl From<ScanError> for EnigmaError {
+    fn from(err: ScanError) -> Self {
+        EnigmaError::Yaml(err)
+    }
+}
+
+impl From<io::Error> for EnigmaError {
+    fn from(err: io::Error) -> Self {
+        EnigmaError::Io(err)
+    }
+}
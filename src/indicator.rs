@@ -0,0 +1,126 @@
+use crate::cli::IndicatorProcedure;
+use crate::error::EnigmaError;
+use crate::machine::Machine;
+use crate::random::Rng;
+use crate::rotor::letter_index;
+use crate::ALPHABET;
+
+impl IndicatorProcedure {
+
+  pub fn repeats(&self) -> usize {
+    // How many times the message key is enciphered
+    match self {
+      IndicatorProcedure::Doubled => 2,
+      IndicatorProcedure::Single => 1,
+    }
+  }
+
+  pub fn indicator_length(&self, num_rotors: usize) -> usize {
+    // Number of letters in the enciphered indicator
+    num_rotors * self.repeats()
+  }
+}
+
+pub fn random_message_key(num_rotors: usize, rng: &mut Rng) -> Vec<usize> {
+  // Choose a message key, one letter per rotor
+  (0..num_rotors).map(|_| rng.below(ALPHABET.len())).collect()
+}
+
+pub fn encipher_indicator(
+  machine: &mut Machine,
+  ground: &[usize],
+  message_key: &[usize],
+  procedure: IndicatorProcedure,
+) -> Result<String, EnigmaError> {
+  // Encipher the message key at the ground setting (Grundstellung)
+  // then set the rotors to the message key for the body
+  let key: String = message_key.iter().map(|position| ALPHABET[*position]).collect();
+
+  machine.set_positions(ground);
+  let indicator = machine.encipher_str(&key.repeat(procedure.repeats()))?;
+  machine.set_positions(message_key);
+
+  Ok(indicator)
+}
+
+pub fn decipher_indicator(
+  machine: &mut Machine,
+  ground: &[usize],
+  indicator: &str,
+  procedure: IndicatorProcedure,
+) -> Result<Vec<usize>, EnigmaError> {
+  // Decipher the indicator at the ground setting and set the rotors to the message key
+  // A doubled key that does not repeat points to a garbled indicator or the wrong ground setting
+  let num_rotors = machine.rotors().len();
+  let invalid = || EnigmaError::InvalidIndicator(indicator.to_string());
+
+  // Every letter must have a key, the machine would skip anything else and leave the key short
+  let length = procedure.indicator_length(num_rotors);
+  if indicator.chars().count() != length || indicator.chars().any(|c| letter_index(c).is_err()) {
+    return Err(invalid());
+  }
+
+  machine.set_positions(ground);
+  let key_letters = machine.encipher_str(indicator)?;
+  let (key, repeated) = key_letters.split_at(num_rotors);
+  if !repeated.is_empty() && repeated != key {
+    machine.set_positions(ground);
+    return Err(invalid());
+  }
+
+  let message_key: Vec<usize> = key.chars().map(letter_index).collect::<Result<_, _>>()?;
+  machine.set_positions(&message_key);
+
+  Ok(message_key)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::plugboard::Plugboard;
+  use crate::rotor::Rotor;
+
+  fn machine() -> Machine {
+    let rotors = vec![
+      Rotor::new("II", "AJDKSIRUXBLHWTMCQGZNPYFVOE", Some(vec!['E'])).unwrap(),
+      Rotor::new("IV", "ESOVPZJAYQUIRHXLNFTGKDCMWB", Some(vec!['J'])).unwrap(),
+      Rotor::new("V", "VZBRGITYUPSDNHLXAWMJQOFECK", Some(vec!['Z'])).unwrap(),
+    ];
+    let reflector = Rotor::new("B", "YRUHQSLDPXNGOKMIEBFZCWVJAT", None).unwrap();
+    let mut machine = Machine::new(rotors, Some(reflector), Plugboard::new());
+    machine.set_rings(&[1, 20, 11]);
+    machine.plugboard_mut().add_connection('A', 'V').unwrap();
+    machine
+  }
+
+  #[test]
+  fn test_indicator_round_trip() {
+    let ground = [22, 25, 0];
+    let message_key = [1, 11, 0];
+
+    for procedure in [IndicatorProcedure::Doubled, IndicatorProcedure::Single] {
+      let mut sender = machine();
+      let indicator = encipher_indicator(&mut sender, &ground, &message_key, procedure).unwrap();
+      assert_eq!(indicator.len(), 3 * procedure.repeats());
+      assert_eq!(sender.window(), "BLA");
+      let ciphertext = sender.encipher_str("FLUGZEUGFUEHRER").unwrap();
+
+      let mut receiver = machine();
+      assert_eq!(decipher_indicator(&mut receiver, &ground, &indicator, procedure).unwrap(), message_key);
+      assert_eq!(receiver.encipher_str(&ciphertext).unwrap(), "FLUGZEUGFUEHRER");
+    }
+  }
+
+  #[test]
+  fn test_garbled_indicator() {
+    let mut machine = machine();
+    let indicator = encipher_indicator(&mut machine, &[0, 0, 0], &[1, 11, 0], IndicatorProcedure::Doubled).unwrap();
+    let last = if indicator.ends_with('Q') { "R" } else { "Q" };
+    let garbled = format!("{}{}", &indicator[..5], last);
+
+    assert!(decipher_indicator(&mut machine, &[0, 0, 0], &garbled, IndicatorProcedure::Doubled).is_err());
+    assert!(decipher_indicator(&mut machine, &[0, 0, 0], &indicator[..3], IndicatorProcedure::Doubled).is_err());
+    assert!(decipher_indicator(&mut machine, &[0, 0, 0], "T1H", IndicatorProcedure::Single).is_err());
+    assert!(decipher_indicator(&mut machine, &[0, 0, 0], "tqh", IndicatorProcedure::Single).is_err());
+  }
+}
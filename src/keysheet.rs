@@ -0,0 +1,343 @@
+use yaml_rust::Yaml;
+
+use crate::catalogue;
+use crate::plugboard;
+use crate::random::Rng;
+use crate::validation::{self, ValidationError};
+use crate::{ALPHABET, MAX_PLUGS};
+
+const KENNGRUPPEN_PER_DAY: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyKey {
+  day: u32,
+  wheel_order: Vec<String>,
+  reflector: Option<String>,
+  rings: Vec<usize>,
+  plugs: Vec<(char, char)>,
+  kenngruppen: Vec<String>,
+}
+
+impl DailyKey {
+
+  pub fn day(&self) -> u32 {
+    self.day
+  }
+
+  pub fn wheel_order(&self) -> &[String] {
+    &self.wheel_order
+  }
+
+  pub fn reflector(&self) -> Option<&str> {
+    self.reflector.as_deref()
+  }
+
+  pub fn rings(&self) -> &[usize] {
+    &self.rings
+  }
+
+  pub fn plugs(&self) -> &[(char, char)] {
+    &self.plugs
+  }
+
+  pub fn kenngruppen(&self) -> &[String] {
+    &self.kenngruppen
+  }
+
+  fn from_yaml(yaml: &Yaml, sheet_reflector: Option<&str>) -> Result<Self, ValidationError> {
+    // Parse a single row of the key sheet
+    let day = yaml["day"]
+      .as_i64()
+      .filter(|day| (1..=31).contains(day))
+      .ok_or_else(|| ValidationError::MissingField("days.day".to_string()))? as u32;
+    let field_name = |name: &str| format!("days.{}.{}", day, name);
+
+    // Wheel order is given as a list or a string such as "I V III"
+    let wheel_order: Vec<String> = match &yaml["wheel_order"] {
+      Yaml::Array(names) => names.iter().filter_map(|name| name.as_str().map(String::from)).collect(),
+      Yaml::String(names) => catalogue::parse_wheel_order(names),
+      _ => return Err(ValidationError::MissingField(field_name("wheel_order"))),
+    };
+
+    let rings = yaml["rings"]
+      .as_str()
+      .ok_or_else(|| ValidationError::MissingField(field_name("rings")))?;
+
+    // Kenngruppen are three letter groups, given as a list or a space separated string
+    let kenngruppen: Vec<String> = match &yaml["kenngruppen"] {
+      Yaml::Array(groups) => groups.iter().filter_map(|group| group.as_str().map(str::to_ascii_uppercase)).collect(),
+      Yaml::String(groups) => groups.split_whitespace().map(str::to_ascii_uppercase).collect(),
+      _ => Vec::new(),
+    };
+    if let Some(group) = kenngruppen.iter().find(|group| group.len() != 3 || !group.chars().all(|c| ALPHABET.contains(&c))) {
+      return Err(ValidationError::InvalidSetting(field_name("kenngruppen"), group.clone()));
+    }
+
+    Ok(DailyKey {
+      day,
+      reflector: yaml["reflector"].as_str().or(sheet_reflector).map(String::from),
+      rings: validation::parse_settings(rings, &field_name("rings"), wheel_order.len())?,
+      plugs: plugboard::parse_plugs(yaml["plugboard"].as_str().unwrap_or(""))?,
+      wheel_order,
+      kenngruppen,
+    })
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeySheet {
+  month: Option<String>,
+  reflector: Option<String>,
+  days: Vec<DailyKey>,
+}
+
+impl KeySheet {
+
+  pub fn from_yaml(yaml: &Yaml) -> Result<Self, ValidationError> {
+    // Parse a monthly key sheet, a reflector given for the sheet applies to every day without one
+    let rows = yaml["days"]
+      .as_vec()
+      .ok_or_else(|| ValidationError::MissingField("days".to_string()))?;
+
+    let mut days: Vec<DailyKey> = Vec::new();
+    for row in rows {
+      let key = DailyKey::from_yaml(row, yaml["reflector"].as_str())?;
+      if days.iter().any(|other| other.day == key.day) {
+        return Err(ValidationError::InvalidSetting("days.day".to_string(), key.day.to_string()));
+      }
+      days.push(key);
+    }
+
+    Ok(KeySheet {
+      month: yaml["month"].as_str().map(String::from),
+      reflector: yaml["reflector"].as_str().map(String::from),
+      days,
+    })
+  }
+
+  pub fn generate(month: &str, rotors: &[String], greek_wheels: &[String], reflector: &str, rng: &mut Rng) -> Result<Self, ValidationError> {
+    // Draw a random key for every day of MONTH (YYYY-MM), listed from the last day down
+    // Greek wheels are only given for the M4, which then takes one ahead of three ROTORS
+    // No wheel order is used twice in the month and every Kenngruppe is different
+    let num_days = days_in_month(month)?;
+    let wheel_orders = rotors.len() * rotors.len().saturating_sub(1) * rotors.len().saturating_sub(2) * greek_wheels.len().max(1);
+    if wheel_orders < num_days as usize {
+      return Err(ValidationError::TooFewWheelOrders(rotors.join(",")));
+    }
+
+    let mut days: Vec<DailyKey> = Vec::new();
+    let mut kenngruppen: Vec<String> = Vec::new();
+
+    for day in (1..=num_days).rev() {
+      // Wheel order, drawn again until it differs from every earlier day
+      let wheel_order = loop {
+        let mut pool = rotors.to_vec();
+        rng.shuffle(&mut pool);
+        pool.truncate(3);
+        if !greek_wheels.is_empty() {
+          pool.insert(0, greek_wheels[rng.below(greek_wheels.len())].clone());
+        }
+        if days.iter().all(|key| key.wheel_order != pool) {
+          break pool;
+        }
+      };
+
+      // Ten plug pairs from a shuffled alphabet, so no letter is used twice
+      let mut letters = ALPHABET;
+      rng.shuffle(&mut letters);
+      let plugs = letters.chunks(2).take(MAX_PLUGS).map(|pair| (pair[0], pair[1])).collect();
+
+      // Kenngruppen, three random letters each
+      let mut groups: Vec<String> = Vec::new();
+      while groups.len() < KENNGRUPPEN_PER_DAY {
+        let group: String = (0..3).map(|_| ALPHABET[rng.below(ALPHABET.len())]).collect();
+        if !kenngruppen.contains(&group) {
+          kenngruppen.push(group.clone());
+          groups.push(group);
+        }
+      }
+
+      days.push(DailyKey {
+        day,
+        reflector: Some(reflector.to_string()),
+        rings: wheel_order.iter().map(|_| rng.below(ALPHABET.len())).collect(),
+        plugs,
+        wheel_order,
+        kenngruppen: groups,
+      });
+    }
+
+    Ok(KeySheet {
+      month: Some(month.to_string()),
+      reflector: Some(reflector.to_string()),
+      days,
+    })
+  }
+
+  pub fn to_yaml(&self) -> String {
+    // Serialize the sheet in the format read by `from_yaml`, one line per day
+    let mut yaml = String::new();
+    if let Some(month) = &self.month {yaml.push_str(&format!("month: \"{}\"\n", month))};
+    if let Some(reflector) = &self.reflector {yaml.push_str(&format!("reflector: \"{}\"\n", reflector))};
+    yaml.push_str("days:\n");
+
+    for key in &self.days {
+      let reflector = match key.reflector() {
+        Some(reflector) if key.reflector != self.reflector => format!(", reflector: \"{}\"", reflector),
+        _ => String::new(),
+      };
+      yaml.push_str(&format!(
+        "  - {{ day: {}, wheel_order: [{}]{}, rings: \"{}\", plugboard: \"{}\", kenngruppen: [{}] }}\n",
+        key.day,
+        key.wheel_order.join(", "),
+        reflector,
+        format_rings(&key.rings),
+        format_plugs(&key.plugs),
+        key.kenngruppen.join(", "),
+      ));
+    }
+    yaml
+  }
+
+  pub fn to_table(&self) -> String {
+    // Lay the sheet out as a printable table, one row per day
+    let wheel_width = self.days.iter().map(|key| key.wheel_order.join(" ").len()).max().unwrap_or(0).max("Wheel order".len());
+    let rings_width = self.days.iter().map(|key| format_rings(&key.rings).len()).max().unwrap_or(0).max("Rings".len());
+    let plugs_width = self.days.iter().map(|key| format_plugs(&key.plugs).len()).max().unwrap_or(0).max("Plugboard".len());
+
+    let mut table = format!(
+      "Key sheet {} | Reflector {}\n\n",
+      self.month.as_deref().unwrap_or("-"),
+      self.reflector.as_deref().unwrap_or("-"),
+    );
+    table.push_str(&format!("Day | {:wheel_width$} | {:rings_width$} | {:plugs_width$} | Kenngruppen\n", "Wheel order", "Rings", "Plugboard"));
+    table.push_str(&format!("----+-{}-+-{}-+-{}-+-{}\n", "-".repeat(wheel_width), "-".repeat(rings_width), "-".repeat(plugs_width), "-".repeat("Kenngruppen".len())));
+
+    for key in &self.days {
+      table.push_str(&format!(
+        "{:>3} | {:wheel_width$} | {:rings_width$} | {:plugs_width$} | {}\n",
+        key.day,
+        key.wheel_order.join(" "),
+        format_rings(&key.rings),
+        format_plugs(&key.plugs),
+        key.kenngruppen.join(" "),
+      ));
+    }
+    table
+  }
+
+  pub fn month(&self) -> Option<&str> {
+    self.month.as_deref()
+  }
+
+  pub fn days(&self) -> &[DailyKey] {
+    &self.days
+  }
+
+  pub fn day(&self, day: u32) -> Result<&DailyKey, ValidationError> {
+    // Select the key for DAY of the month
+    self.days
+      .iter()
+      .find(|key| key.day == day)
+      .ok_or(ValidationError::UnknownDay(day))
+  }
+}
+
+fn days_in_month(month: &str) -> Result<u32, ValidationError> {
+  // Number of days in a month written as YYYY-MM
+  let invalid = || ValidationError::InvalidMonth(month.to_string());
+  let (year, month) = month.split_once('-').ok_or_else(invalid)?;
+  let year: u32 = year.parse().map_err(|_| invalid())?;
+  let month: u32 = month.parse().map_err(|_| invalid())?;
+
+  match month {
+    2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => Ok(29),
+    2 => Ok(28),
+    4 | 6 | 9 | 11 => Ok(30),
+    1..=12 => Ok(31),
+    _ => Err(invalid()),
+  }
+}
+
+pub fn format_rings(rings: &[usize]) -> String {
+  // Ring settings as numbers, e.g. "14 09 24"
+  rings.iter().map(|ring| format!("{:02}", ring + 1)).collect::<Vec<String>>().join(" ")
+}
+
+pub fn format_plugs(plugs: &[(char, char)]) -> String {
+  // Plug pairs, e.g. "SZ GT DV"
+  plugs.iter().map(|(c1, c2)| format!("{}{}", c1, c2)).collect::<Vec<String>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use yaml_rust::YamlLoader;
+
+  fn sheet(yaml: &str) -> Result<KeySheet, ValidationError> {
+    KeySheet::from_yaml(&YamlLoader::load_from_str(yaml).unwrap()[0])
+  }
+
+  #[test]
+  fn test_key_sheet() {
+    let sheet = sheet("
+month: 2026-11
+reflector: B
+days:
+  - { day: 31, wheel_order: [I, V, III], rings: 14 09 24, plugboard: SZ GT DV KU FO MY EW JN IX LQ, kenngruppen: [wny, dgy, hxl, tkx] }
+  - { day: 30, wheel_order: IV III II, rings: 05 26 02, reflector: C, plugboard: IS EV MX RW DT UZ JQ AO CH NY }
+").unwrap();
+
+    assert_eq!(sheet.month(), Some("2026-11"));
+    assert_eq!(sheet.days().len(), 2);
+
+    let key = sheet.day(31).unwrap();
+    assert_eq!(key.wheel_order(), ["I", "V", "III"]);
+    assert_eq!(key.reflector(), Some("B"));
+    assert_eq!(key.rings(), [13, 8, 23]);
+    assert_eq!(key.plugs().len(), 10);
+    assert_eq!(key.kenngruppen(), ["WNY", "DGY", "HXL", "TKX"]);
+
+    assert_eq!(sheet.day(30).unwrap().wheel_order(), ["IV", "III", "II"]);
+    assert_eq!(sheet.day(30).unwrap().reflector(), Some("C"));
+    assert!(sheet.day(14).is_err());
+  }
+
+  #[test]
+  fn test_invalid_key_sheet() {
+    assert!(sheet("month: 2026-11").is_err());
+    assert!(sheet("days: [{ day: 1, wheel_order: [I, II, III], rings: AA }]").is_err());
+    assert!(sheet("days: [{ day: 1, wheel_order: [I, II, III], rings: AAA, plugboard: AB AC }]").is_err());
+    assert!(sheet("days: [{ day: 1, wheel_order: [I, II, III], rings: AAA, kenngruppen: [ABCD] }]").is_err());
+    assert!(sheet("days: [{ day: 1, wheel_order: [I, II, III], rings: AAA }, { day: 1, wheel_order: [I, II, III], rings: AAA }]").is_err());
+  }
+
+  #[test]
+  fn test_generated_key_sheet() {
+    let rotors: Vec<String> = ["I", "II", "III", "IV", "V"].map(String::from).to_vec();
+    let sheet = KeySheet::generate("2026-11", &rotors, &[], "B", &mut Rng::new(14)).unwrap();
+
+    // Reproducible from the seed and readable back from its own format
+    assert_eq!(sheet, KeySheet::generate("2026-11", &rotors, &[], "B", &mut Rng::new(14)).unwrap());
+    assert_ne!(sheet, KeySheet::generate("2026-11", &rotors, &[], "B", &mut Rng::new(15)).unwrap());
+    assert_eq!(sheet, KeySheet::from_yaml(&YamlLoader::load_from_str(&sheet.to_yaml()).unwrap()[0]).unwrap());
+
+    assert_eq!(sheet.days().len(), 30);
+    assert_eq!(sheet.days()[0].day(), 30);
+    for (i, key) in sheet.days().iter().enumerate() {
+      assert!(sheet.days()[..i].iter().all(|other| other.wheel_order() != key.wheel_order()));
+      assert_eq!(key.plugs().len(), MAX_PLUGS);
+      assert_eq!(plugboard::parse_plugs(&format_plugs(key.plugs())).unwrap().len(), MAX_PLUGS);
+    }
+    assert_eq!(sheet.to_table().lines().count(), 2 + 2 + 30);
+  }
+
+  #[test]
+  fn test_generate_needs_enough_wheel_orders() {
+    let rotors: Vec<String> = ["I", "II", "III"].map(String::from).to_vec();
+    assert!(KeySheet::generate("2026-11", &rotors, &[], "B", &mut Rng::new(14)).is_err());
+    assert!(KeySheet::generate("2026-13", &rotors, &[], "B", &mut Rng::new(14)).is_err());
+    assert_eq!(days_in_month("2028-02").unwrap(), 29);
+    assert_eq!(days_in_month("2100-02").unwrap(), 28);
+  }
+}
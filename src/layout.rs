@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::validation::ValidationError;
+use crate::view::Frame;
+use crate::ALPHABET;
+
+// Line between the header and the anchor grid of a layout file
+const SEPARATOR: &str = "---";
+
+// Where the rotor windows, lamps, keys and plug sockets sit in the ASCII art
+//
+// A layout file has a header giving the rows of each view, then a grid laid over the art:
+//
+//   top: 0-16
+//   front: 16-23
+//   ---
+//   <grid with 1-4 on rotor windows, A-Z on lamps and a-z on keys (top view)
+//    and A-Z on plug sockets (front view)>
+#[derive(Debug, Clone)]
+pub struct Layout {
+  top_view: Range<usize>,
+  front_view: Range<usize>,
+  top: HashMap<char, (usize, usize)>,
+  plugboard: HashMap<char, (usize, usize)>,
+}
+
+impl Layout {
+
+  pub fn parse(text: &str, num_rotors: usize) -> Result<Self, ValidationError> {
+    // Read the view rows from the header and the anchors from the grid
+    // Anchors are (row, column) with the column counted in characters
+    let invalid = |reason: String| ValidationError::InvalidLayout(reason);
+    let (header, grid) = text
+      .split_once(&format!("\n{}\n", SEPARATOR))
+      .ok_or_else(|| invalid(format!("missing '{}' line before the grid", SEPARATOR)))?;
+
+    let mut top_view = None;
+    let mut front_view = None;
+    for line in header.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+      let (key, rows) = line.split_once(':').ok_or_else(|| invalid(format!("unexpected line '{}'", line)))?;
+      let rows = parse_rows(rows.trim()).ok_or_else(|| invalid(format!("invalid rows '{}', expected e.g. 0-16", rows.trim())))?;
+      match key.trim() {
+        "top" => top_view = Some(rows),
+        "front" => front_view = Some(rows),
+        key => return Err(invalid(format!("unknown view '{}'", key))),
+      }
+    }
+    let top_view = top_view.ok_or_else(|| invalid("missing top view rows".to_string()))?;
+    let front_view = front_view.ok_or_else(|| invalid("missing front view rows".to_string()))?;
+
+    let mut layout = Layout {
+      top_view,
+      front_view,
+      top: HashMap::new(),
+      plugboard: HashMap::new(),
+    };
+
+    for (row, line) in grid.lines().enumerate() {
+      for (col, c) in line.chars().enumerate() {
+        // Rows shared by both views (the divider) belong to the top view
+        let mapping = match c {
+          '1'..='9' | 'A'..='Z' | 'a'..='z' if layout.top_view.contains(&row) => &mut layout.top,
+          'A'..='Z' if layout.front_view.contains(&row) => &mut layout.plugboard,
+          _ => continue,
+        };
+        if mapping.insert(c, (row, col)).is_some() {
+          return Err(invalid(format!("'{}' is marked more than once", c)));
+        }
+      }
+    }
+
+    // Every lamp, key, socket and rotor window must be placed
+    let windows = (1..=num_rotors).filter_map(|n| char::from_digit(n as u32, 10));
+    let lamps_and_keys = ALPHABET.iter().flat_map(|c| [*c, c.to_ascii_lowercase()]);
+    for c in windows.chain(lamps_and_keys) {
+      if !layout.top.contains_key(&c) {
+        return Err(invalid(format!("no '{}' in the top view", c)));
+      }
+    }
+    if let Some(c) = ALPHABET.iter().find(|c| !layout.plugboard.contains_key(c)) {
+      return Err(invalid(format!("no plug socket '{}' in the front view", c)));
+    }
+
+    Ok(layout)
+  }
+
+  pub fn fits(&self, frame: &Frame) -> Result<(), ValidationError> {
+    // Check the views and anchors lie inside the art
+    // Rotor windows also need the rows above and below for the turning animation
+    let invalid = |reason: String| ValidationError::InvalidLayout(reason);
+    for view in [&self.top_view, &self.front_view] {
+      if view.end > frame.len() {
+        return Err(invalid(format!("rows {}-{} run past the {} lines of art", view.start, view.end - 1, frame.len())));
+      }
+    }
+
+    for (c, (row, col)) in self.top.iter().chain(self.plugboard.iter()) {
+      let mut rows = match c.is_ascii_digit() {
+        true => row.wrapping_sub(1)..row + 2,
+        false => *row..row + 1,
+      };
+      if rows.any(|row| frame.get(row).is_none_or(|line| *col >= line.len())) {
+        return Err(invalid(format!("'{}' at line {}, column {} is outside the art", c, row + 1, col + 1)));
+      }
+    }
+
+    Ok(())
+  }
+
+  pub fn top_view(&self) -> Range<usize> {
+    self.top_view.clone()
+  }
+
+  pub fn front_view(&self) -> Range<usize> {
+    self.front_view.clone()
+  }
+
+  pub fn top(&self) -> &HashMap<char, (usize, usize)> {
+    &self.top
+  }
+
+  pub fn plugboard(&self) -> &HashMap<char, (usize, usize)> {
+    &self.plugboard
+  }
+}
+
+fn parse_rows(rows: &str) -> Option<Range<usize>> {
+  // Parse an inclusive row range such as "0-16"
+  let (first, last) = rows.split_once('-')?;
+  let (first, last): (usize, usize) = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+  (first <= last).then_some(first..last + 1)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const GRID: &str = "top: 0-1\nfront: 2-2\n---\n 1 ABCDEFGHIJKLMNOPQRSTUVWXYZ\n abcdefghijklmnopqrstuvwxyz\nZYXWVUTSRQPONMLKJIHGFEDCBA\n";
+
+  #[test]
+  fn test_parse() {
+    let layout = Layout::parse(GRID, 1).unwrap();
+    assert_eq!(layout.top_view(), 0..2);
+    assert_eq!(layout.front_view(), 2..3);
+    assert_eq!(layout.top()[&'1'], (0, 1));
+    assert_eq!(layout.top()[&'A'], (0, 3));
+    assert_eq!(layout.top()[&'z'], (1, 26));
+    assert_eq!(layout.plugboard()[&'A'], (2, 25));
+  }
+
+  #[test]
+  fn test_invalid_layout() {
+    assert!(Layout::parse(GRID, 2).is_err());
+    assert!(Layout::parse(&GRID.replace("Q", " "), 1).is_err());
+    assert!(Layout::parse(&GRID.replace("A\n", "AA\n"), 1).is_err());
+    assert!(Layout::parse(&GRID.replace("---", ""), 1).is_err());
+    assert!(Layout::parse(&GRID.replace("top: 0-1", "top: 1-0"), 1).is_err());
+  }
+}
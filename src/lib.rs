@@ -1,19 +1,47 @@
+pub mod bombe;
+pub mod catalogue;
 pub mod cli;
 pub mod config;
+pub mod error;
+pub mod indicator;
+pub mod keysheet;
+pub mod layout;
 pub mod model;
 pub mod view;
 pub mod loader;
+pub mod machine;
+pub mod menu;
 pub mod rotor;
+pub mod search;
 pub mod plugboard;
+pub mod radio;
+pub mod random;
+pub mod solver;
 pub mod message;
+pub mod ngram;
+pub mod state;
 pub mod validation;
 
-use std::error::Error;
+use std::io;
+use std::path::Path;
+use yaml_rust::Yaml;
 
+use cli::{Command, SheetFormat};
 use config::Config;
+use error::EnigmaError;
 use model::EnigmaModel;
+use ngram::NgramModel;
 use view::EnigmaView;
+use machine::Machine;
+use menu::Menu;
+use state::Snapshot;
+use bombe::Bombe;
+use catalogue::Catalogue;
+use keysheet::{DailyKey, KeySheet};
+use random::Rng;
 use rotor::Rotor;
+use search::Search;
+use validation::ValidationError;
 use plugboard::Plugboard;
 
 pub const MAX_PLUGS: usize = 10;
@@ -27,64 +55,324 @@ pub const ALPHABET: [char; 26] = [
 ];
 
 pub const INSTRUCTIONS: [&str; 2] = [
-  "(ESC) => Exit | (CHAR) => Add Plug | (ENTER) => FINISH\r\n\r\n",
-  "(ESC) => Exit | (CHAR) => Type | (ENTER) => Save/Wipe Message\r\n\r\n",
+  "(ESC) => Cancel | (CHAR) => Add/Remove Plug | (ENTER) => FINISH\r\n\r\n",
+  "(ESC) => Exit | (CHAR) => Type | (BACKSPACE) => Undo | (NUM) => Rotate | (F-NUM) => Ring | (CTRL+P) => Plugboard | (CTRL+S) => Save State | (ENTER) => Save/Wipe Message\r\n\r\n",
 ];
 
-pub fn run (config: Config)-> Result<(), Box<dyn Error>> {
+pub fn run (config: Config)-> Result<(), EnigmaError> {
   
-  // Load ASCII art
-  let frame = loader::ascii_art()?;
+  // Load permutations, built in unless a config directory overrides them
+  let config_dirs = loader::config_dirs(config.config_dir());
+  let permutations = loader::permutations_yaml(&config_dirs)?;
 
-  // Load permutations
-  let permutations = loader::permutations_yaml()?;
+  // Load rotor and reflector catalogue
+  let catalogue = Catalogue::from_yaml(&permutations);
 
-  // Load ASCII character mapping for the top view
-  let ascii_mapping_top = loader::ascii_mapping_top();
+  // Generate a key sheet instead of running the machine
+  if let Some(Command::Keysheet { month, seed, format }) = config.command() {
+    return generate_key_sheet(&config, &catalogue, month, *seed, *format)
+  }
 
-  // Load ASCII character mapping for the plugboard view
-  let ascii_mapping_plugboard = loader::ascii_mapping_plugboard();
+  // Search for the key with the bombe instead of running the machine
+  if let Some(Command::Bombe { crib, at, ciphertext }) = config.command() {
+    return run_bombe(&config, &catalogue, crib, *at, ciphertext)
+  }
 
-  // Create rotors
-  let rotors: Vec<Rotor> = permutations["rotor"]
-    .as_vec()
-    .expect("Expected rotor_permutations to be an array")
-    .iter()
-    .map(|perm| Rotor::new(perm
-        .as_str()
-        .expect("Expected string")))
-    .collect();
+  // List the crib placements instead of running the machine
+  if let Some(Command::Cribs { crib, loops, ciphertext }) = config.command() {
+    return find_cribs(crib, *loops, ciphertext)
+  }
+
+  // Search for the key from the ciphertext alone instead of running the machine
+  if let Some(Command::Solve { ngram, keep, ciphertext }) = config.command() {
+    let model = NgramModel::from_corpus(&loader::corpus(&config_dirs)?, *ngram as usize);
+    return run_solver(&config, &catalogue, &model, *keep, ciphertext)
+  }
 
-  // Create reflector
-  let reflector: Option<Rotor> = Some(Rotor::new(permutations["reflector"]
-    .as_str()
-    .expect("Expected string")));
+  // Select the day's key from the key sheet
+  let daily_key = match (config.key_sheet(), config.day()) {
+    (Some(path), Some(day)) => Some(loader::key_sheet(path)?.day(day)?.clone()),
+    _ => None,
+  };
 
-  // Create plugboard
-  let plugboard: Plugboard = Plugboard::new();
+  // Create the machine from a saved state, or from the options and permutations file
+  let machine = match config.state() {
+    Some(path) => Snapshot::load(path)?.restore(&catalogue)?,
+    None => build_machine(&config, &permutations, &catalogue, daily_key.as_ref())?,
+  };
+  let num_rotors = machine.rotors().len();
+
+  // Load ASCII art with a window for every rotor
+  let frame = loader::ascii_art(num_rotors, &config_dirs)?;
+
+  // Load the layout marking rotor windows, lamps, keys and plug sockets in the art
+  let layout = loader::layout(num_rotors, &config_dirs, &frame)?;
 
   // Create view
   let view = EnigmaView::new(
     frame,
-    ascii_mapping_top,
-    ascii_mapping_plugboard,
+    layout,
   );
 
   // Create model
+  let headless = config.is_headless();
+  let radio = config.is_radio();
   let mut enigma = EnigmaModel::new(
     view, 
-    rotors, 
-    reflector,
-    plugboard,
+    machine,
     config,
   ); 
+  if let Some(key) = &daily_key {
+    enigma.set_kenngruppen(key.kenngruppen());
+  }
+
+  // Encipher stdin to stdout without the view
+  if headless {
+    match radio {
+      true => enigma.encipher_radio(io::stdin().lock(), io::stdout().lock())?,
+      false => enigma.encipher_stream(io::stdin().lock(), io::stdout().lock())?,
+    }
+    return Ok(())
+  }
 
   // Wire plugboard
-  enigma.wire_plugboard();
+  enigma.wire_plugboard()?;
 
   // Run the enigma machine
-  enigma.start_typing();
+  enigma.start_typing()?;
+
+  Ok(())
+
+}
+
+fn generate_key_sheet(config: &Config, catalogue: &Catalogue, month: &str, seed: Option<u64>, format: SheetFormat) -> Result<(), EnigmaError> {
+  // Print a random key sheet for the month, drawing rotors from --rotors (all five army rotors by default)
+  let rotors = catalogue.rotor_range(config.rotors().unwrap_or("I-V"))?;
+  let greek_wheels: Vec<String> = match config.is_m4() {
+    true => catalogue.greek_wheel_names().into_iter().map(String::from).collect(),
+    false => Vec::new(),
+  };
+
+  // Check the reflector fits the machine the sheet is for
+  let reflector_name = config.reflector().unwrap_or(if config.is_m4() { "B-Thin" } else { "B" });
+  let reflector = catalogue.reflector(reflector_name, config.is_m4())?;
+
+  let mut rng = seed.map(Rng::new).unwrap_or_else(Rng::from_time);
+  let sheet = KeySheet::generate(month, &rotors, &greek_wheels, reflector.get_name(), &mut rng)?;
+
+  match format {
+    SheetFormat::Yaml => print!("{}", sheet.to_yaml()),
+    SheetFormat::Table => print!("{}", sheet.to_table()),
+  }
+  Ok(())
+}
+
+fn run_bombe(config: &Config, catalogue: &Catalogue, crib: &str, at: usize, path: &Path) -> Result<(), EnigmaError> {
+  // Run the bombe over every wheel order drawn from --rotors and print the stops, with --m4 behind each Greek wheel
+  // The drums take the rings from --rings (01 by default), stops are tried on the machine with the plugs found
+  let ciphertext = loader::ciphertext(path)?;
+  let menu = Menu::new(crib, &ciphertext, at)?;
+  let rotors = catalogue.rotor_range(config.rotors().unwrap_or("I-V"))?;
+  let orders = bombe::wheel_orders(&rotors, 3);
+  let wheel_orders: Vec<Vec<String>> = match config.is_m4() {
+    true => catalogue
+      .greek_wheel_names()
+      .into_iter()
+      .flat_map(|greek| orders.iter().map(move |order| [vec![greek.to_string()], order.clone()].concat()))
+      .collect(),
+    false => orders,
+  };
+
+  // Check the reflector fits the machine the drums stand for
+  let reflector_name = config.reflector().unwrap_or(if config.is_m4() { "B-Thin" } else { "B" });
+  catalogue.reflector(reflector_name, config.is_m4())?;
+  let rings = config.rings()
+    .map(|rings| validation::parse_settings(rings, "rings", if config.is_m4() { 4 } else { 3 }))
+    .transpose()?
+    .unwrap_or_default();
+
+  print!("{}", menu.describe());
+  let search = search(config, catalogue, wheel_orders, reflector_name, &rings);
+  let stops = search.run(
+    |machine| Bombe::new(&menu, machine),
+    |bombe, machine, positions| bombe.test_at(machine, positions),
+    report_progress,
+  )?;
+
+  for stop in &stops {
+    // Decipher the start of the message at the stop, plugs the menu says nothing about are left out
+    let mut machine = search.machine(stop.wheel_order())?;
+    machine.set_positions(stop.positions());
+    for (c1, c2) in stop.plugs() {
+      machine.plugboard_mut().add_connection(*c1, *c2)?;
+    }
+    let plaintext = machine.encipher_str(&ciphertext)?;
+    println!(
+      "Stop {:<12} {}  {:<40} {}",
+      stop.wheel_order().join(","),
+      stop.window(),
+      keysheet::format_plugs(stop.plugs()),
+      plaintext.chars().take(40).collect::<String>(),
+    );
+  }
+
+  println!("{} stops", stops.len());
+  Ok(())
+}
+
+fn search<'a>(config: &Config, catalogue: &'a Catalogue, wheel_orders: Vec<Vec<String>>, reflector: &str, rings: &[usize]) -> Search<'a> {
+  // Search over the wheel orders with the number of workers from --threads
+  let mut search = Search::new(catalogue, wheel_orders, reflector, rings);
+  if let Some(threads) = config.threads() {
+    search.set_threads(threads);
+  }
+  search
+}
+
+fn report_progress(done: usize, total: usize) {
+  // Show how much of the keyspace has been searched, on one line of stderr
+  eprint!("\rSearched {:>3}% of {} positions", done * 100 / total.max(1), total);
+  if done == total {
+    eprintln!();
+  }
+}
+
+fn find_cribs(crib: &str, loops: bool, path: &Path) -> Result<(), EnigmaError> {
+  // Print every offset the crib fits, with LOOPS the loops of its menu
+  // Offsets whose menu has the most independent loops are marked with a star, they give the bombe the most to test
+  let ciphertext = loader::ciphertext(path)?;
+  let placements = menu::placements(crib, &ciphertext);
+  let menus: Vec<Menu> = placements
+    .iter()
+    .map(|at| Menu::new(crib, &ciphertext, *at))
+    .collect::<Result<_, _>>()?;
+
+  println!(
+    "Crib {} fits at {} of {} offsets",
+    crib.to_ascii_uppercase(),
+    placements.len(),
+    (ciphertext.len() + 1).saturating_sub(crib.len()),
+  );
+
+  let menu_loops: Vec<Vec<Vec<usize>>> = menus.iter().map(|menu| if loops { menu.loops() } else { Vec::new() }).collect();
+  let most = menus.iter().map(Menu::independent_loops).max().unwrap_or_default();
+
+  for (menu, menu_loops) in menus.iter().zip(&menu_loops) {
+    let cipher: String = ciphertext.chars().skip(menu.at()).take(crib.len()).collect();
+    if !loops {
+      println!("{:>6}  {}", menu.at(), cipher);
+      continue;
+    }
+
+    // The longest loop is only known when every loop was found
+    let independent = menu.independent_loops();
+    let longest = match menu_loops.first() {
+      Some(first) if menu_loops.len() < menu::MAX_LOOPS => format!(", longest {}", menu.format_loop(first)),
+      _ => String::new(),
+    };
+    println!(
+      "{} {:>4}  {}  {} independent loops, {} loops{}",
+      if independent == most && most > 0 { "*" } else { " " },
+      menu.at(),
+      cipher,
+      independent,
+      menu::loop_count(menu_loops),
+      longest,
+    );
+  }
+
+  Ok(())
+}
+
+fn run_solver(config: &Config, catalogue: &Catalogue, model: &NgramModel, keep: usize, path: &Path) -> Result<(), EnigmaError> {
+  // Run the ciphertext-only attack over every wheel order drawn from --rotors and print the best keys
+  let ciphertext = loader::ciphertext(path)?;
+  let rotors = catalogue.rotor_range(config.rotors().unwrap_or("I-V"))?;
+  let reflector_name = config.reflector().unwrap_or("B");
+  let search = search(config, catalogue, bombe::wheel_orders(&rotors, 3), reflector_name, &[]);
+  let solutions = solver::solve(&search, &ciphertext, model, keep, report_progress)?;
 
+  for solution in solutions {
+    println!(
+      "{:<12} rings {}  {}  {:<30} {:>6.2}  {}",
+      solution.wheel_order().join(","),
+      keysheet::format_rings(solution.rings()),
+      solution.window(),
+      keysheet::format_plugs(solution.plugs()),
+      solution.score(),
+      solution.plaintext().chars().take(40).collect::<String>(),
+    );
+  }
   Ok(())
+}
+
+fn build_machine(config: &Config, permutations: &Yaml, catalogue: &Catalogue, daily_key: Option<&DailyKey>) -> Result<Machine, EnigmaError> {
+  // Set up the machine from the command line or key sheet, falling back to the permutations file
+
+  // Four rotor wheel orders and --m4 take their defaults from the M4 section
+  let chosen_order = config.rotors()
+    .map(catalogue::parse_wheel_order)
+    .or(daily_key.map(|key| key.wheel_order().to_vec()));
+  if let Some(order) = chosen_order.as_ref().filter(|order| config.is_m4() && order.len() != 4) {
+    return Err(ValidationError::InvalidM4WheelOrder(order.join(",")).into());
+  }
+  let m4 = config.is_m4() || chosen_order.as_ref().is_some_and(|order| order.len() == 4);
+  let defaults = if m4 { &permutations["m4"] } else { permutations };
+
+  // Select wheel order, command line and key sheet take precedence over permutations file
+  let wheel_order: Vec<String> = match chosen_order {
+    Some(order) => order,
+    None => defaults["wheel_order"]
+      .as_vec()
+      .ok_or_else(|| ValidationError::MissingField("wheel_order".to_string()))?
+      .iter()
+      .filter_map(|name| name.as_str().map(String::from))
+      .collect(),
+  };
+
+  // Create rotors
+  let mut rotors: Vec<Rotor> = catalogue.wheel_order(&wheel_order)?;
+
+  // Apply ring settings, command line and key sheet take precedence over permutations file
+  let rings = match daily_key {
+    Some(key) => Some(key.rings().to_vec()),
+    None => config.rings()
+      .or(defaults["rings"].as_str())
+      .map(|rings| validation::parse_settings(rings, "rings", rotors.len()))
+      .transpose()?,
+  };
+  for (rotor, ring) in rotors.iter_mut().zip(rings.unwrap_or_default()) {
+    rotor.set_ring(ring);
+  }
+
+  // Apply initial positions, command line takes precedence over permutations file
+  if let Some(positions) = config.positions().or(defaults["positions"].as_str()) {
+    let positions = validation::parse_settings(positions, "positions", rotors.len())?;
+    for (rotor, position) in rotors.iter_mut().zip(positions) {
+      rotor.set_offset(position);
+    }
+  }
+
+  // Create reflector, command line and key sheet take precedence over permutations file
+  let reflector_name = config.reflector()
+    .or(daily_key.and_then(|key| key.reflector()))
+    .or(defaults["reflector"].as_str())
+    .ok_or_else(|| ValidationError::MissingField("reflector".to_string()))?;
+  let reflector: Option<Rotor> = Some(catalogue.reflector(reflector_name, rotors.len() == 4)?);
+
+  // Create plugboard, command line and key sheet take precedence over permutations file
+  let plugs = match daily_key {
+    Some(key) => key.plugs().to_vec(),
+    None => config.plugs()
+      .or(defaults["plugboard"].as_str())
+      .map(plugboard::parse_plugs)
+      .transpose()?
+      .unwrap_or_default(),
+  };
+  let mut plugboard: Plugboard = Plugboard::new();
+  for (c1, c2) in plugs {
+    plugboard.add_connection(c1, c2)?;
+  }
 
+  Ok(Machine::new(rotors, reflector, plugboard))
 }
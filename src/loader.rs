@@ -1,29 +1,99 @@
+use std::env;
 use std::fs;
-use std::path::Path;
-use std::error::Error;
-use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use yaml_rust::{Yaml, YamlLoader};
-use crossterm::style::Color;
+use crate::error::EnigmaError;
+use crate::keysheet::KeySheet;
+use crate::layout::Layout;
+use crate::validation::{validate_yaml, ValidationError};
+use crate::view::Frame;
 
-use crate::validation::validate_yaml;
 
+// Built-in copies of the configuration files, used unless a config directory overrides them
+const PERMUTATIONS: &str = include_str!("../permutations.yaml");
+const ASCII: &str = include_str!("../assets/ascii.txt");
+const ASCII_M4: &str = include_str!("../assets/ascii_m4.txt");
+const LAYOUT: &str = include_str!("../assets/ascii.layout");
+const LAYOUT_M4: &str = include_str!("../assets/ascii_m4.layout");
+const GERMAN: &str = include_str!("../assets/german.txt");
 
-pub fn permutations_yaml() -> Result<Yaml, Box<dyn Error>> {
+// Environment variable naming a config directory
+pub const CONFIG_ENV: &str = "ENIGMA_CONFIG";
+
+pub fn config_dirs(config_dir: Option<&Path>) -> Vec<PathBuf> {
+    // Directories that can override the built-in files, highest priority first:
+    // --config, then $ENIGMA_CONFIG, then $XDG_CONFIG_HOME/enigma (or ~/.config/enigma)
+    let xdg_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("enigma"));
+
+    config_dir
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(env::var_os(CONFIG_ENV).filter(|dir| !dir.is_empty()).map(PathBuf::from))
+        .chain(xdg_dir)
+        .collect()
+}
+
+fn read_config_file(config_dirs: &[PathBuf], file_name: &str, built_in: &str) -> Result<(String, String), EnigmaError> {
+    // Read FILE_NAME from the first config directory that has it, or fall back to the built-in copy
+    // Returns the contents and where they came from
+    for dir in config_dirs {
+        let path = dir.join(file_name);
+        if path.is_file() {
+            return Ok((fs::read_to_string(&path)?, path.display().to_string()));
+        }
+    }
+    Ok((built_in.to_string(), format!("built-in {}", file_name)))
+}
+
+pub fn permutations_yaml(config_dirs: &[PathBuf]) -> Result<Yaml, EnigmaError> {
     // Load the permutations.yaml file and parse it into a YAML structure
-    let permutations_path = Path::new("permutations.yaml");
-    let yaml_str = fs::read_to_string(permutations_path)?;
+    let (yaml_str, source) = read_config_file(config_dirs, "permutations.yaml", PERMUTATIONS)?;
     let docs = YamlLoader::load_from_str(&yaml_str)?;
-    let yaml = docs.into_iter().next().ok_or_else(|| Box::<dyn Error>::from("Empty YAML file"))?;
+    let yaml = docs.into_iter().next().ok_or(ValidationError::EmptyFile(source))?;
     validate_yaml(&yaml)?;
     Ok(yaml)
 }
 
-pub fn ascii_art() -> Result<Vec<Vec<(char, Option<Color>)>>, Box<dyn Error>> {
-    // Load ascii art from "ascii.txt" file
+pub fn key_sheet(path: &Path) -> Result<KeySheet, EnigmaError> {
+    // Load a monthly key sheet and parse every day's key
+    let yaml_str = fs::read_to_string(path)?;
+    let docs = YamlLoader::load_from_str(&yaml_str)?;
+    let yaml = docs.into_iter().next().ok_or_else(|| ValidationError::EmptyFile(path.display().to_string()))?;
+    Ok(KeySheet::from_yaml(&yaml)?)
+}
+
+pub fn ciphertext(path: &Path) -> Result<String, EnigmaError> {
+    // Read the letters of a ciphertext file, skipping the header lines of a radio message
+    let text = fs::read_to_string(path)?;
+    Ok(text
+        .lines()
+        .filter(|line| !line.contains('='))
+        .flat_map(str::chars)
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_uppercase())
+        .collect())
+}
+
+pub fn corpus(config_dirs: &[PathBuf]) -> Result<String, EnigmaError> {
+    // Load the sample plaintext that n-gram statistics are counted from
+    let (corpus, _) = read_config_file(config_dirs, "assets/german.txt", GERMAN)?;
+    Ok(corpus)
+}
+
+pub fn ascii_art(num_rotors: usize, config_dirs: &[PathBuf]) -> Result<Frame, EnigmaError> {
+    // Load ascii art from "ascii.txt" file, or "ascii_m4.txt" for the four rotor machine
 
-    let ascii_path = Path::new("assets/ascii.txt");
+    let (ascii_str, _) = if num_rotors == 4 {
+        read_config_file(config_dirs, "assets/ascii_m4.txt", ASCII_M4)?
+    } else {
+        read_config_file(config_dirs, "assets/ascii.txt", ASCII)?
+    };
     
-    let ascii_art: Vec<Vec<(char, Option<Color>)>> = fs::read_to_string(ascii_path)?
+    let ascii_art: Frame = ascii_str
         .lines()
         .map(|line| line
             .chars()
@@ -34,111 +104,66 @@ pub fn ascii_art() -> Result<Vec<Vec<(char, Option<Color>)>>, Box<dyn Error>> {
     Ok(ascii_art)
 }
 
-pub fn ascii_mapping_top() -> HashMap<char, (usize, usize)> {
-    // Create map of ASCII characters that need to be located
-    
-    // Rotor and keyboard origin coordinates
-    let (r0x, r0y): (usize, usize) = (5, 14);
-    let (k0x, k0y): (usize, usize) = (9, 10);
-    
-    HashMap::from([
-
-        // Rotor relative locations
-        ('1', (0 + r0x, 0 + r0y)),
-        ('2', (0 + r0x, 7 + r0y)),
-        ('3', (0 + r0x, 14 + r0y)),
-        
-        // Lamp relative locations
-        ('Q', (0 + k0x, 0 + k0y)),
-        ('W', (0 + k0x, 4 + k0y)),
-        ('E', (0 + k0x, 8 + k0y)),
-        ('R', (0 + k0x, 12 + k0y)),
-        ('T', (0 + k0x, 16 + k0y)),
-        ('Z', (0 + k0x, 20 + k0y)),
-        ('U', (0 + k0x, 24 + k0y)),
-        ('I', (0 + k0x, 28 + k0y)),
-        ('O', (0 + k0x, 32 + k0y)),
-        ('A', (1 + k0x, 2 + k0y)),
-        ('S', (1 + k0x, 6 + k0y)),
-        ('D', (1 + k0x, 10 + k0y)),
-        ('F', (1 + k0x, 14 + k0y)),
-        ('G', (1 + k0x, 18 + k0y)),
-        ('H', (1 + k0x, 22 + k0y)),
-        ('J', (1 + k0x, 26 + k0y)),
-        ('K', (1 + k0x, 30 + k0y)),
-        ('P', (2 + k0x, 0 + k0y)),
-        ('Y', (2 + k0x, 4 + k0y)),
-        ('X', (2 + k0x, 8 + k0y)),
-        ('C', (2 + k0x, 12 + k0y)),
-        ('V', (2 + k0x, 16 + k0y)),
-        ('B', (2 + k0x, 20 + k0y)),
-        ('N', (2 + k0x, 24 + k0y)),
-        ('M', (2 + k0x, 28 + k0y)),
-        ('L', (2 + k0x, 32 + k0y)),
-        
-        // Keyboard relative locations
-        ('q', (4 + k0x, 0 + k0y)),
-        ('w', (4 + k0x, 4 + k0y)),
-        ('e', (4 + k0x, 8 + k0y)),
-        ('r', (4 + k0x, 12 + k0y)),
-        ('t', (4 + k0x, 16 + k0y)),
-        ('z', (4 + k0x, 20 + k0y)),
-        ('u', (4 + k0x, 24 + k0y)),
-        ('i', (4 + k0x, 28 + k0y)),
-        ('o', (4 + k0x, 32 + k0y)),
-        ('a', (5 + k0x, 2 + k0y)),
-        ('s', (5 + k0x, 6 + k0y)),
-        ('d', (5 + k0x, 10 + k0y)),
-        ('f', (5 + k0x, 14 + k0y)),
-        ('g', (5 + k0x, 18 + k0y)),
-        ('h', (5 + k0x, 22 + k0y)),
-        ('j', (5 + k0x, 26 + k0y)),
-        ('k', (5 + k0x, 30 + k0y)),
-        ('p', (6 + k0x, 0 + k0y)),
-        ('y', (6 + k0x, 4 + k0y)),
-        ('x', (6 + k0x, 8 + k0y)),
-        ('c', (6 + k0x, 12 + k0y)),
-        ('v', (6 + k0x, 16 + k0y)),
-        ('b', (6 + k0x, 20 + k0y)),
-        ('n', (6 + k0x, 24 + k0y)),
-        ('m', (6 + k0x, 28 + k0y)),
-        ('l', (6 + k0x, 32 + k0y)),
-    ])
+pub fn layout(num_rotors: usize, config_dirs: &[PathBuf], frame: &Frame) -> Result<Layout, EnigmaError> {
+    // Load the screen layout for the art, "ascii.layout" or "ascii_m4.layout" for the four rotor machine
+    let (layout_str, _) = if num_rotors == 4 {
+        read_config_file(config_dirs, "assets/ascii_m4.layout", LAYOUT_M4)?
+    } else {
+        read_config_file(config_dirs, "assets/ascii.layout", LAYOUT)?
+    };
+
+    let layout = Layout::parse(&layout_str, num_rotors)?;
+    layout.fits(frame)?;
+    Ok(layout)
 }
 
-pub fn ascii_mapping_plugboard() -> HashMap<char, (usize, usize)> {
-    // Create map of ASCII characters that need to be located
-    
-    // Plugboard origin coordinates
-    let (p0x, p0y): (usize, usize) = (18, 10);
-    HashMap::from([
-
-        // Plugboard relative locations
-        ('Q', (0 + p0x, 0 + p0y)),
-        ('W', (0 + p0x, 4 + p0y)),
-        ('E', (0 + p0x, 8 + p0y)),
-        ('R', (0 + p0x, 12 + p0y)),
-        ('T', (0 + p0x, 16 + p0y)),
-        ('Z', (0 + p0x, 20 + p0y)),
-        ('U', (0 + p0x, 24 + p0y)),
-        ('I', (0 + p0x, 28 + p0y)),
-        ('O', (0 + p0x, 32 + p0y)),
-        ('A', (2 + p0x, 2 + p0y)),
-        ('S', (2 + p0x, 6 + p0y)),
-        ('D', (2 + p0x, 10 + p0y)),
-        ('F', (2 + p0x, 14 + p0y)),
-        ('G', (2 + p0x, 18 + p0y)),
-        ('H', (2 + p0x, 22 + p0y)),
-        ('J', (2 + p0x, 26 + p0y)),
-        ('K', (2 + p0x, 30 + p0y)),
-        ('P', (4 + p0x, 0 + p0y)),
-        ('Y', (4 + p0x, 4 + p0y)),
-        ('X', (4 + p0x, 8 + p0y)),
-        ('C', (4 + p0x, 12 + p0y)),
-        ('V', (4 + p0x, 16 + p0y)),
-        ('B', (4 + p0x, 20 + p0y)),
-        ('N', (4 + p0x, 24 + p0y)),
-        ('M', (4 + p0x, 28 + p0y)),
-        ('L', (4 + p0x, 32 + p0y)),
-    ])
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_files() {
+        let yaml = permutations_yaml(&[]).unwrap();
+        assert!(yaml["rotors"]["I"]["wiring"].as_str().is_some());
+        assert_eq!(ascii_art(3, &[]).unwrap().len(), ASCII.lines().count());
+        assert_eq!(ascii_art(4, &[]).unwrap().len(), ASCII_M4.lines().count());
+    }
+
+    #[test]
+    fn test_built_in_layouts() {
+        // The built-in layouts place everything where the art draws it
+        for num_rotors in [3, 4] {
+            let frame = ascii_art(num_rotors, &[]).unwrap();
+            let layout = layout(num_rotors, &[], &frame).unwrap();
+            assert_eq!(layout.top_view(), 0..17);
+            assert_eq!(layout.front_view(), 16..24);
+            assert_eq!(layout.top()[&'3'], (5, 28));
+            assert_eq!(layout.top()[&'K'], (10, 40));
+            assert_eq!(layout.top()[&'p'], (15, 10));
+            assert_eq!(layout.plugboard()[&'L'], (22, 42));
+            for (c, (row, col)) in layout.top() {
+                if c.is_alphabetic() {
+                    assert_eq!(frame[*row][*col].0.to_ascii_uppercase(), c.to_ascii_uppercase());
+                }
+            }
+            for (row, col) in layout.plugboard().values() {
+                assert_eq!(frame[*row][*col].0, ':');
+            }
+        }
+    }
+
+    #[test]
+    fn test_config_dir_override() {
+        let dir = env::temp_dir().join(format!("enigma_config_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("permutations.yaml"), "rotors:\n  X: { wiring: ABCDEFGHIJKLMNOPQRSTUVWXYZ }\nreflectors: {}\n").unwrap();
+
+        // The override wins over the built-in file, missing files still come from the binary
+        let dirs = [PathBuf::from("/nonexistent"), dir.clone()];
+        assert!(permutations_yaml(&dirs).unwrap()["rotors"]["X"].as_hash().is_some());
+        assert_eq!(ascii_art(3, &dirs).unwrap().len(), ASCII.lines().count());
+
+        assert_eq!(config_dirs(Some(&dir))[0], dir);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
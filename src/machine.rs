@@ -0,0 +1,272 @@
+use crate::rotor::{self, Rotor};
+use crate::plugboard::Plugboard;
+use crate::ALPHABET;
+use crate::error::EnigmaError;
+
+#[derive(Debug)]
+pub struct Machine {
+  rotors: Vec<Rotor>,
+  reflector: Option<Rotor>,
+  plugboard: Plugboard,
+  steps: Vec<bool>,
+}
+
+impl Machine {
+
+  pub fn new(rotors: Vec<Rotor>, reflector: Option<Rotor>, plugboard: Plugboard) -> Self {
+    // Rotors are ordered slowest (leftmost) first
+    Machine {
+      steps: vec![false; rotors.len()],
+      rotors,
+      reflector,
+      plugboard,
+    }
+  }
+
+  pub fn rotors(&self) -> &[Rotor] {
+    &self.rotors
+  }
+
+  pub fn rotors_mut(&mut self) -> &mut [Rotor] {
+    &mut self.rotors
+  }
+
+  pub fn reflector(&self) -> Option<&Rotor> {
+    self.reflector.as_ref()
+  }
+
+  pub fn plugboard(&self) -> &Plugboard {
+    &self.plugboard
+  }
+
+  pub fn plugboard_mut(&mut self) -> &mut Plugboard {
+    &mut self.plugboard
+  }
+
+  pub fn positions(&self) -> Vec<usize> {
+    // Return the rotor positions, slowest rotor first
+    self.rotors.iter().map(|rotor| rotor.get_offset()).collect()
+  }
+
+  pub fn window(&self) -> String {
+    // Return the letters shown in the rotor windows, slowest rotor first
+    self.rotors.iter().map(|rotor| ALPHABET[rotor.get_offset()]).collect()
+  }
+
+  pub fn set_positions(&mut self, positions: &[usize]) {
+    // Set the rotor positions, slowest rotor first
+    for (rotor, position) in self.rotors.iter_mut().zip(positions) {
+      rotor.set_offset(*position);
+    }
+  }
+
+  pub fn set_rings(&mut self, rings: &[usize]) {
+    // Set the ring settings, slowest rotor first
+    for (rotor, ring) in self.rotors.iter_mut().zip(rings) {
+      rotor.set_ring(*ring);
+    }
+  }
+
+  pub fn rotate(&mut self, rotor_num: usize) {
+    // Manually rotate a single rotor by one position, without carrying
+    self.rotors[rotor_num].advance();
+  }
+
+  pub fn step(&mut self) -> Vec<bool> {
+    // Step the rotors as for a key press and return which rotors moved
+    self.advance();
+    self.steps.clone()
+  }
+
+  pub fn advance(&mut self) {
+    // Step the rotors as for a key press, without handing out which rotors moved as `step` does
+    rotor::turn(&mut self.rotors, &mut self.steps);
+  }
+
+  pub fn unstep(&mut self, steps: &[bool]) {
+    // Undo a key press, STEPS are the rotors that moved as returned by `step`
+    for (rotor, step) in self.rotors.iter_mut().zip(steps) {
+      if *step {rotor.retreat();}
+    }
+  }
+
+  pub fn signal(&self, c: char) -> Result<char, EnigmaError> {
+    // Pass the signal for C through the machine without stepping
+    let index = rotor::letter_index(c)? as u8;
+    Ok(ALPHABET[self.signal_index(index) as usize])
+  }
+
+  pub fn signal_index(&self, mut index: u8) -> u8 {
+    // Pass the letter at INDEX through the machine without stepping, by table lookups alone
+    index = self.plugboard.permute_index(index);
+    for rotor in self.rotors.iter().rev() {
+      index = rotor.forward_index(index);
+    }
+    if let Some(reflector) = &self.reflector {
+      index = reflector.forward_index(index);
+    }
+    for rotor in &self.rotors {
+      index = rotor.reverse_index(index);
+    }
+    self.plugboard.permute_index(index)
+  }
+
+  pub fn signal_with<F: FnMut(&str, char)>(&self, mut c: char, mut observe: F) -> Result<char, EnigmaError> {
+    // Pass the signal for C through the machine, reporting every stage to OBSERVE
+
+    // Pass C through the plugboard
+    c = self.plugboard.permutation(c)?;
+    observe("PLUG", c);
+
+    // Pass C through the rotors in the forward direction
+    for i in (0..self.rotors.len()).rev() {
+      c = self.rotors[i].forward_permutation(c)?;
+      observe(&format!("R{}", i), c);
+    }
+
+    // Apply the reflector to C if present
+    if let Some(reflector) = &self.reflector {
+      c = reflector.forward_permutation(c)?;
+      observe("REFLECT", c);
+    }
+
+    // Pass C through the rotors in the reverse direction
+    for i in 0..self.rotors.len() {
+      c = self.rotors[i].reverse_permutation(c)?;
+      observe(&format!("R{}", i), c);
+    }
+
+    // Pass C through the plugboard
+    c = self.plugboard.permutation(c)?;
+    observe("PLUG", c);
+
+    Ok(c)
+  }
+
+  pub fn encipher_char(&mut self, c: char) -> Result<char, EnigmaError> {
+    // Press the key for C, stepping the rotors before the signal passes
+    // Characters without a key are rejected before the rotors move
+    let index = rotor::letter_index(c)? as u8;
+    self.advance();
+    Ok(ALPHABET[self.signal_index(index) as usize])
+  }
+
+  pub fn encipher_letters(&mut self, letters: &[u8]) -> Vec<u8> {
+    // Press the keys for LETTERS, given as alphabet positions, and return the lamps that light
+    letters
+      .iter()
+      .map(|letter| {
+        self.advance();
+        self.signal_index(*letter)
+      })
+      .collect()
+  }
+
+  pub fn encipher_str(&mut self, text: &str) -> Result<String, EnigmaError> {
+    // Encipher the letters of TEXT, anything that is not A-Z is skipped
+    Ok(rotor::text(&self.encipher_letters(&rotor::letters(text))))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn machine(positions: &[usize], rings: &[usize]) -> Machine {
+    let rotors = vec![
+      Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap(),
+      Rotor::new("II", "AJDKSIRUXBLHWTMCQGZNPYFVOE", Some(vec!['E'])).unwrap(),
+      Rotor::new("III", "BDFHJLCPRTXVZNYEIWGAKMUSQO", Some(vec!['V'])).unwrap(),
+    ];
+    let reflector = Rotor::new("B", "YRUHQSLDPXNGOKMIEBFZCWVJAT", None).unwrap();
+    let mut machine = Machine::new(rotors, Some(reflector), Plugboard::new());
+    machine.set_positions(positions);
+    machine.set_rings(rings);
+    machine
+  }
+
+  #[test]
+  fn test_known_ciphertext() {
+    assert_eq!(machine(&[0, 0, 0], &[0, 0, 0]).encipher_str("AAAAA").unwrap(), "BDZGO");
+    assert_eq!(machine(&[0, 0, 0], &[1, 1, 1]).encipher_str("AAAAA").unwrap(), "EWTYX");
+  }
+
+  #[test]
+  fn test_encipher_letters() {
+    // The index path lights the same lamps as the letter path and steps the same way
+    let mut by_letter = machine(&[0, 3, 20], &[2, 7, 19]);
+    let mut by_index = machine(&[0, 3, 20], &[2, 7, 19]);
+    by_letter.plugboard_mut().add_connection('Q', 'E').unwrap();
+    by_index.plugboard_mut().add_connection('Q', 'E').unwrap();
+
+    let plaintext = "DERWINDDREHTAUFWEST";
+    let lamps: String = plaintext.chars().map(|c| by_letter.encipher_char(c).unwrap()).collect();
+    assert_eq!(rotor::text(&by_index.encipher_letters(&rotor::letters(plaintext))), lamps);
+    assert_eq!(by_index.window(), by_letter.window());
+  }
+
+  #[test]
+  fn test_reciprocal() {
+    let plaintext = "WETTERVORHERSAGEBISKAYA";
+    let mut sender = machine(&[16, 4, 21], &[5, 11, 20]);
+    sender.plugboard_mut().add_connection('A', 'V').unwrap();
+    sender.plugboard_mut().add_connection('B', 'S').unwrap();
+    let ciphertext = sender.encipher_str(plaintext).unwrap();
+
+    let mut receiver = machine(&[16, 4, 21], &[5, 11, 20]);
+    receiver.plugboard_mut().add_connection('A', 'V').unwrap();
+    receiver.plugboard_mut().add_connection('B', 'S').unwrap();
+    assert_eq!(receiver.encipher_str(&ciphertext).unwrap(), plaintext);
+  }
+
+  #[test]
+  fn test_no_self_encipherment() {
+    let mut machine = machine(&[0, 0, 0], &[0, 0, 0]);
+    for c in ALPHABET.iter().cycle().take(26 * 30) {
+      assert_ne!(machine.encipher_char(*c).unwrap(), *c);
+    }
+  }
+
+  #[test]
+  fn test_double_step_window() {
+    let mut machine = machine(&[0, 3, 20], &[0, 0, 0]);
+    machine.encipher_str("AAA").unwrap();
+    assert_eq!(machine.window(), "BFX");
+  }
+
+  #[test]
+  fn test_unstep_through_double_step() {
+    let mut machine = machine(&[0, 3, 20], &[0, 0, 0]);
+    let history: Vec<Vec<bool>> = (0..4).map(|_| machine.step()).collect();
+    assert_eq!(machine.window(), "BFY");
+
+    for steps in history.iter().rev() {
+      machine.unstep(steps);
+    }
+    assert_eq!(machine.window(), "ADU");
+  }
+
+  #[test]
+  fn test_invalid_character() {
+    let mut machine = machine(&[0, 0, 0], &[0, 0, 0]);
+    assert!(machine.encipher_char('1').is_err());
+    assert_eq!(machine.window(), "AAA");
+  }
+
+  #[test]
+  fn test_m4_compatible_with_three_rotor_machine() {
+    // Beta at A with the thin B reflector behaves like reflector B
+    let rotors = vec![
+      Rotor::new("Beta", "LEYJVCNIXWPBQMDRTAKZGFUHOS", None).unwrap(),
+      Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap(),
+      Rotor::new("II", "AJDKSIRUXBLHWTMCQGZNPYFVOE", Some(vec!['E'])).unwrap(),
+      Rotor::new("III", "BDFHJLCPRTXVZNYEIWGAKMUSQO", Some(vec!['V'])).unwrap(),
+    ];
+    let reflector = Rotor::new("B-Thin", "ENKQAUYWJICOPBLMDXZVFTHRGS", None).unwrap();
+    let mut m4 = Machine::new(rotors, Some(reflector), Plugboard::new());
+
+    let plaintext = "ABCDEFGHIJKLMNOPQRSTUVWXYZ".repeat(30);
+    assert_eq!(m4.encipher_str(&plaintext).unwrap(), machine(&[0, 0, 0], &[0, 0, 0]).encipher_str(&plaintext).unwrap());
+    assert!(m4.window().starts_with('A'));
+  }
+}
@@ -0,0 +1,277 @@
+use crate::rotor::letter_index;
+use crate::validation::ValidationError;
+use crate::ALPHABET;
+
+// A crib letter joined to the cipher letter at the same position of the message
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Link {
+  plain: usize,
+  cipher: usize,
+  position: usize,
+}
+
+impl Link {
+
+  pub fn plain(&self) -> usize {
+    self.plain
+  }
+
+  pub fn cipher(&self) -> usize {
+    self.cipher
+  }
+
+  pub fn position(&self) -> usize {
+    // Position of the letter in the whole ciphertext, counted from 0
+    self.position
+  }
+
+  pub fn other(&self, letter: usize) -> usize {
+    // The letter at the other end of the link
+    if letter == self.plain { self.cipher } else { self.plain }
+  }
+}
+
+// Most loops looked for in a menu, a long crib over few letters can close very many
+pub const MAX_LOOPS: usize = 10_000;
+
+// The menu of a crib placed against the ciphertext, as wired up on the bombe
+#[derive(Debug, Clone)]
+pub struct Menu {
+  crib: String,
+  at: usize,
+  links: Vec<Link>,
+}
+
+impl Menu {
+
+  pub fn new(crib: &str, ciphertext: &str, at: usize) -> Result<Self, ValidationError> {
+    // Pair every crib letter with the cipher letter AT positions further on
+    // A letter paired with itself cannot happen, the reflector never lets a key light its own lamp
+    let invalid = |reason: String| ValidationError::InvalidCrib(crib.to_string(), reason);
+    let crib = crib.to_ascii_uppercase();
+    if crib.is_empty() {
+      return Err(invalid("the crib is empty".to_string()));
+    }
+
+    let cipher_letters: Vec<char> = ciphertext.chars().collect();
+    if at + crib.chars().count() > cipher_letters.len() {
+      return Err(invalid(format!("it runs past the {} letters of ciphertext", cipher_letters.len())));
+    }
+
+    let mut links = Vec::new();
+    for (i, (p, c)) in crib.chars().zip(&cipher_letters[at..]).enumerate() {
+      let plain = letter_index(p).map_err(|_| invalid(format!("'{}' is not a letter A-Z", p)))?;
+      let cipher = letter_index(*c).map_err(|_| invalid(format!("'{}' is not a letter A-Z", c)))?;
+      if plain == cipher {
+        return Err(invalid(format!("{} would encipher to itself at position {}", p, at + i)));
+      }
+      links.push(Link { plain, cipher, position: at + i });
+    }
+
+    Ok(Menu {
+      crib,
+      at,
+      links,
+    })
+  }
+
+  pub fn crib(&self) -> &str {
+    &self.crib
+  }
+
+  pub fn at(&self) -> usize {
+    self.at
+  }
+
+  pub fn links(&self) -> &[Link] {
+    &self.links
+  }
+
+  pub fn links_at(&self, letter: usize) -> impl Iterator<Item = (usize, &Link)> {
+    // The links joined to LETTER, with their index in the menu
+    self.links.iter().enumerate().filter(move |(_, link)| link.plain == letter || link.cipher == letter)
+  }
+
+  pub fn test_letter(&self) -> usize {
+    // The letter with the most links, where the bombe's test register is connected
+    (0..ALPHABET.len())
+      .max_by_key(|letter| (self.links_at(*letter).count(), usize::MAX - letter))
+      .unwrap_or_default()
+  }
+
+  pub fn independent_loops(&self) -> usize {
+    // Number of loops no other loops combine into: links - letters + connected pieces of the menu
+    // Each one is a separate check on the bombe, unlike `loops` it is cheap for any crib
+    let mut parent: Vec<usize> = (0..ALPHABET.len()).collect();
+
+    let mut letters = [false; ALPHABET.len()];
+    let mut pieces = 0;
+    for link in &self.links {
+      for letter in [link.plain, link.cipher] {
+        if !letters[letter] {
+          letters[letter] = true;
+          pieces += 1;
+        }
+      }
+      let (a, b) = (root(&mut parent, link.plain), root(&mut parent, link.cipher));
+      if a != b {
+        parent[a] = b;
+        pieces -= 1;
+      }
+    }
+
+    let num_letters = letters.iter().filter(|used| **used).count();
+    self.links.len() + pieces - num_letters
+  }
+
+  pub fn loops(&self) -> Vec<Vec<usize>> {
+    // Every closed path through the menu that uses a link at most once, longest first
+    // Each loop is given by its links in order, starting and ending on its lowest letter
+    // The search stops at MAX_LOOPS, so a list that long is only the first loops found
+    let mut loops: Vec<Vec<usize>> = Vec::new();
+    for start in 0..ALPHABET.len() {
+      self.find_loops(start, start, &mut Vec::new(), &mut loops);
+    }
+    loops.sort_by_key(|links| std::cmp::Reverse(links.len()));
+    loops
+  }
+
+  fn find_loops(&self, start: usize, letter: usize, path: &mut Vec<usize>, loops: &mut Vec<Vec<usize>>) {
+    // Walk on from LETTER through letters above START, recording each return to START
+    // A loop is found once in each direction, only the one with the smaller first link is kept
+    for (i, link) in self.links_at(letter) {
+      if loops.len() >= MAX_LOOPS || path.contains(&i) {
+        continue;
+      }
+      let next = link.other(letter);
+      let visited = path.iter().any(|j| self.links[*j].plain == next || self.links[*j].cipher == next);
+
+      if next == start && path.first().is_none_or(|first| *first < i) {
+        loops.push(path.iter().copied().chain([i]).collect());
+      } else if next > start && !visited {
+        path.push(i);
+        self.find_loops(start, next, path, loops);
+        path.pop();
+      }
+    }
+  }
+
+  pub fn format_loop(&self, links: &[usize]) -> String {
+    // A loop as its letters with the positions of the links between them, e.g. "E 3 T 5 R 1 E"
+    let Some(first) = links.first() else {
+      return String::new();
+    };
+    let link = &self.links[*first];
+    let mut letter = match links.get(1).map(|second| &self.links[*second]) {
+      Some(second) if second.plain == link.plain || second.cipher == link.plain => link.cipher,
+      _ => link.plain,
+    };
+
+    let mut formatted = ALPHABET[letter].to_string();
+    for i in links {
+      letter = self.links[*i].other(letter);
+      formatted.push_str(&format!(" {} {}", self.links[*i].position, ALPHABET[letter]));
+    }
+    formatted
+  }
+
+  pub fn describe(&self) -> String {
+    // Crib above the ciphertext it is placed against, with the test letter and loops
+    let cipher: String = self.links.iter().map(|link| ALPHABET[link.cipher]).collect();
+    format!(
+      "Menu at {}: {} links, {} independent loops, test register {}\n  {}\n  {}\n",
+      self.at,
+      self.links.len(),
+      self.independent_loops(),
+      ALPHABET[self.test_letter()],
+      self.crib,
+      cipher,
+    )
+  }
+}
+
+fn root(parent: &mut [usize], mut letter: usize) -> usize {
+  // The letter that stands for the piece of the menu LETTER is in, shortening the path on the way
+  while parent[letter] != letter {
+    parent[letter] = parent[parent[letter]];
+    letter = parent[letter];
+  }
+  letter
+}
+
+pub fn loop_count(loops: &[Vec<usize>]) -> String {
+  // Number of LOOPS found by `Menu::loops`, shown as a lower bound when the search was cut off
+  match loops.len() >= MAX_LOOPS {
+    true => format!("\u{2265}{}", MAX_LOOPS),
+    false => loops.len().to_string(),
+  }
+}
+
+pub fn placements(crib: &str, ciphertext: &str) -> Vec<usize> {
+  // Offsets where the crib can sit under the ciphertext, no letter may encipher to itself
+  let crib: Vec<char> = crib.to_ascii_uppercase().chars().collect();
+  let cipher: Vec<char> = ciphertext.chars().collect();
+  if crib.is_empty() || crib.len() > cipher.len() {
+    return Vec::new();
+  }
+
+  (0..=cipher.len() - crib.len())
+    .filter(|at| crib.iter().zip(&cipher[*at..]).all(|(p, c)| p != c))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_menu() {
+    let menu = Menu::new("wetter", "QQRWGXAYP", 2).unwrap();
+    assert_eq!(menu.links().len(), 6);
+    assert_eq!(menu.links()[0], Link { plain: 22, cipher: 17, position: 2 });
+
+    // E, R, T and W have two links each, the first of them is chosen
+    assert_eq!(menu.test_letter(), 4);
+    assert_eq!(menu.links_at(4).map(|(i, _)| i).collect::<Vec<_>>(), vec![1, 4]);
+  }
+
+  #[test]
+  fn test_loops() {
+    // E-W-R-E closes through links 0 (W-R), 1 (E-W) and 5 (R-E), T and A hang off it
+    let menu = Menu::new("WETTRR", "RWGXAE", 0).unwrap();
+    assert_eq!(menu.loops(), vec![vec![1, 0, 5]]);
+    assert_eq!(menu.format_loop(&menu.loops()[0]), "E 1 W 0 R 5 E");
+
+    // Two links between the same letters make a loop of two
+    let menu = Menu::new("AB", "BA", 0).unwrap();
+    assert_eq!(menu.loops(), vec![vec![0, 1]]);
+    assert_eq!(loop_count(&menu.loops()), "1");
+  }
+
+  #[test]
+  fn test_independent_loops() {
+    // One loop E-W-R-E, with T-G and X-A hanging off as separate pieces
+    assert_eq!(Menu::new("WETTRR", "RWGXAE", 0).unwrap().independent_loops(), 1);
+    assert_eq!(Menu::new("WETTER", "QWGXAE", 0).unwrap().independent_loops(), 0);
+
+    // A long crib over few letters closes far more loops than are enumerated
+    let menu = Menu::new(&"ABCDEF".repeat(20), &"BCDEFA".repeat(20), 0).unwrap();
+    assert_eq!(menu.independent_loops(), 120 - 6 + 1);
+    assert_eq!(loop_count(&menu.loops()), "\u{2265}10000");
+  }
+
+  #[test]
+  fn test_placements() {
+    assert_eq!(placements("AB", "ABAB"), vec![1]);
+    assert_eq!(placements("AB", "CDCD"), vec![0, 1, 2]);
+    assert!(placements("ABCDE", "CDCD").is_empty());
+  }
+
+  #[test]
+  fn test_invalid_crib() {
+    assert!(Menu::new("WETTER", "ABCDE", 0).is_err());
+    assert!(Menu::new("WETTER", "QWERTYUIOP", 1).is_err());
+    assert!(Menu::new("WET TER", "ABCDEFGHIJ", 0).is_err());
+    assert!(Menu::new("", "ABCDEFGHIJ", 0).is_err());
+  }
+}
@@ -1,55 +1,170 @@
-use::std::fs;
+use::std::fs::{self, OpenOptions};
+use::std::io::{self, Write};
+use::std::path::Path;
+
+use crate::radio::{self, RadioMessage};
+
+// Line length of the on-screen message, line breaks included
+const LINE_LENGTH: usize = 54;
 
 pub struct Message {
   content: String,
-  formatted: String,
+  typed: String,
+}
+
+impl Default for Message {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl Message {
   pub fn new() -> Self {
     Message {
       content: String::new(),
-      formatted: String::new()
+      typed: String::new(),
     }      
   }
 
   pub fn add(&mut self, c: char) {
     // Add C to the message and go to new line if necessary
-    if (self.content.len() % 54) == 0 {self.content.push_str("\r\n")};
-    self.content.push(c);
+    push_wrapped(&mut self.content, c);
+  }
+
+  pub fn add_typed(&mut self, c: char) {
+    // Record the key C that was typed, wrapped in line with the message
+    push_wrapped(&mut self.typed, c);
   }
   
+  pub fn pop(&mut self) -> Option<char> {
+    // Remove the last character and the key typed for it
+    pop_wrapped(&mut self.typed);
+    pop_wrapped(&mut self.content)
+  }
+
+  pub fn last(&self) -> Option<char> {
+    // Return the last character of the message
+    self.content.chars().last().filter(|c| c.is_ascii_alphabetic())
+  }
+
   pub fn clear(&mut self) {
     self.content.clear();
+    self.typed.clear();
   }
 
   pub fn read(&self) -> &str {
     &self.content
   }
 
-  pub fn print(&mut self) {
-    self.format();
-    fs::create_dir_all("print").expect("Unable to create print dir");
-    fs::write("print/msg.txt", self.formatted.clone()).expect("Unable to write file");
+  pub fn transcript(&self) -> String {
+    // Typed keys above the lamp letters, one pair of lines for every line of the message
+    self.typed
+      .split("\r\n")
+      .zip(self.content.split("\r\n"))
+      .skip(1)
+      .map(|(typed, lamps)| format!("\r\nIN  {}\r\nOUT {}", typed, lamps))
+      .collect()
   }
 
-  fn format(&mut self) {
-    // Format the message into enigma style
-    self.formatted = self.content
-      .chars()
-      .filter(|&c| c != '\n')
-      .filter(|&c| c != '\r')
-      .enumerate()
-      .flat_map(|(i, c)| {
-        let mut chunk = vec![c];
-  
-        if (i + 1) % 40 == 0 {
-          chunk.push('\n');
-        } else if (i + 1) % 5 == 0 {
-          chunk.push(' ');
-        }
-        chunk
-      })
-      .collect()
+  pub fn letters(&self) -> String {
+    // Return the message without line breaks
+    self.content.chars().filter(|c| !c.is_whitespace()).collect()
+  }
+
+  pub fn radio(&self, indicator: &[String], kenngruppe: Option<&str>) -> RadioMessage {
+    // Lay the message out in radio format, with header and five letter groups
+    RadioMessage::new(
+      &radio::time_of_origin(),
+      indicator.to_vec(),
+      kenngruppe.map(String::from),
+      &self.letters(),
+    )
+  }
+}
+
+fn push_wrapped(buffer: &mut String, c: char) {
+  // Add C to BUFFER and go to new line if necessary
+  if buffer.len().is_multiple_of(LINE_LENGTH) {buffer.push_str("\r\n")};
+  buffer.push(c);
+}
+
+fn pop_wrapped(buffer: &mut String) -> Option<char> {
+  // Remove the last character, and the line break added before it if any
+  let c = buffer.pop()?;
+  if buffer.ends_with("\r\n") {
+    buffer.truncate(buffer.len() - 2);
   }
-}
\ No newline at end of file
+  Some(c)
+}
+
+pub fn save(path: &Path, text: &str) -> io::Result<()> {
+  // Write TEXT to PATH, replacing an earlier message
+  create_parent(path)?;
+  fs::write(path, text)
+}
+
+pub fn append_to_log(path: &Path, header: &str, text: &str) -> io::Result<()> {
+  // Add TEXT under HEADER to the end of the log at PATH, earlier entries are never changed
+  create_parent(path)?;
+  let mut log = OpenOptions::new().create(true).append(true).open(path)?;
+  writeln!(log, "=== {} ===", header)?;
+  writeln!(log, "{}", text)
+}
+
+fn create_parent(path: &Path) -> io::Result<()> {
+  // Create the directory the file goes in
+  match path.parent() {
+    Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+    _ => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_pop_across_line_break() {
+    let mut message = Message::new();
+    for _ in 0..60 {message.add('A')};
+    let before = message.read().to_string();
+
+    message.add('B');
+    assert_eq!(message.pop(), Some('B'));
+    assert_eq!(message.read(), before);
+
+    // Removing every letter leaves the message empty, line breaks included
+    while message.pop().is_some() {}
+    assert_eq!(message.read(), "");
+  }
+
+  #[test]
+  fn test_transcript() {
+    let mut message = Message::new();
+    for (typed, lamp) in "HELLO".chars().zip("ILBDA".chars()).cycle().take(60) {
+      message.add_typed(typed);
+      message.add(lamp);
+    }
+
+    let transcript = message.transcript();
+    let lines: Vec<&str> = transcript.split("\r\n").skip(1).collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("IN  HELLOHELLO") && lines[1].starts_with("OUT ILBDAILBDA"));
+    assert_eq!(lines[0].len(), lines[1].len());
+    assert_eq!(lines[2].len(), lines[3].len());
+
+    message.pop();
+    assert_eq!(message.transcript().len(), transcript.len() - 2);
+  }
+
+  #[test]
+  fn test_append_to_log() {
+    let path = std::env::temp_dir().join(format!("enigma_log_{}", std::process::id())).join("messages.log");
+    append_to_log(&path, "first", "ABCDE\n").unwrap();
+    append_to_log(&path, "second", "FGHIJ\n").unwrap();
+
+    let log = fs::read_to_string(&path).unwrap();
+    assert_eq!(log, "=== first ===\nABCDE\n\n=== second ===\nFGHIJ\n\n");
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+  }
+}
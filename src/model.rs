@@ -1,45 +1,84 @@
+use std::io::{BufRead, Read, Write};
 use std::time::Duration;
-use crossterm::event::{self, KeyCode, KeyEventKind};
+use crossterm::event::{self, KeyCode, KeyEventKind, KeyModifiers};
 use event::{poll, read};
 use event::Event;
 
+use crate::cli::IndicatorProcedure;
 use crate::config::Config;
-use crate::rotor::Rotor;
-use crate::plugboard::Plugboard;
+use crate::error::EnigmaError;
+use crate::indicator;
+use crate::machine::Machine;
+use crate::radio::{self, RadioMessage};
+use crate::random::Rng;
+use crate::validation;
 use crate::view::EnigmaView;
-use crate::message::Message;
+use crate::message::{self, Message};
+use crate::state::Snapshot;
 use crate::{ALPHABET, MAX_PLUGS};
 
 pub struct EnigmaModel {
   view: EnigmaView,
-  rotors: Vec<Rotor>,
-  reflector: Option<Rotor>,
-  plugboard: Plugboard,
+  machine: Machine,
   message: Message,
+  history: Vec<Vec<bool>>,
+  indicator: Option<String>,
+  sent_indicator: Vec<String>,
+  ground: Option<Vec<usize>>,
+  message_settings: Option<Snapshot>,
+  kenngruppen: Vec<String>,
+  rng: Rng,
   config: Config,
 }
 
 impl EnigmaModel {
 
   pub fn new(view: EnigmaView, 
-             rotors: Vec<Rotor>,
-             reflector: Option<Rotor>,
-             plugboard: Plugboard,
+             machine: Machine,
              config: Config) -> Self {
-    EnigmaModel {
+    let mut model = EnigmaModel {
       view,
-      rotors,
-      reflector,
-      plugboard,
+      machine,
       message: Message::new(),
+      history: Vec::new(),
+      indicator: config.indicator().map(|_| String::new()),
+      sent_indicator: Vec::new(),
+      ground: None,
+      message_settings: None,
+      kenngruppen: Vec::new(),
+      rng: Rng::from_time(),
       config,
+    };
+    model.set_rotor_windows();
+    model.prompt_indicator();
+    model
+  }
+
+  pub fn set_kenngruppen(&mut self, kenngruppen: &[String]) {
+    // Kenngruppen of the day, one is sent with every message
+    self.kenngruppen = kenngruppen.to_vec();
+  }
+
+  fn set_rotor_windows(&mut self) {
+    // Show the current rotor positions in the rotor windows
+    for i in 0..self.machine.rotors().len() {
+      let rotor_char = char::from_digit((i + 1) as u32, 10).unwrap();
+      let letter = self.get_rotor_letter(i);
+      self.view.set_rotor(rotor_char, letter);
     }
   }
 
-  pub fn start_typing(&mut self) {
-    // Enter typing mode
+  pub fn start_typing(&mut self) -> Result<(), EnigmaError> {
+    // Enter typing mode, the view is ended even if typing fails
+    if self.config.is_display()  {self.view.start("top")?};
+    let typed = self.typing_loop();
+    self.end_view(typed)
+  }
+
+  fn typing_loop(&mut self) -> Result<(), EnigmaError> {
+    // Handle key presses until Esc
 
-    if self.config.is_display()  {self.view.start("top"); self.view.flip(self.config.should_show_instructions())};
+    if self.config.is_display()  {self.view.flip(self.config.should_show_instructions())?};
     
     loop {
       if let Ok(true) = poll(Duration::from_millis(100)) {
@@ -51,21 +90,158 @@ impl EnigmaModel {
           
           match key_event.code {
             KeyCode::Esc => break,
-            KeyCode::Enter => self.save_and_wipe_message(),
-            KeyCode::Char(c) => self.handle_character(c),
+            KeyCode::Char('s') if key_event.modifiers.contains(KeyModifiers::CONTROL) => self.save_state_now()?,
+            KeyCode::Char('p') if key_event.modifiers.contains(KeyModifiers::CONTROL) => self.rewire_plugboard()?,
+            KeyCode::Enter => self.save_and_wipe_message()?,
+            KeyCode::Backspace => self.backspace()?,
+            KeyCode::Char(c) => self.handle_character(c)?,
+            KeyCode::F(n) => self.adjust_ring(n as usize)?,
             _ => {}
           }
         }
       }
     }
 
-    if self.config.is_display()  {self.view.end()};
+    self.save_state()
+  }
+
+  fn end_view(&mut self, result: Result<(), EnigmaError>) -> Result<(), EnigmaError> {
+    // End the view (leaving raw mode) and report the first error
+    if self.config.is_display()  {
+      let ended = self.view.end();
+      result?;
+      ended?;
+      return Ok(())
+    }
+    result
+  }
+
+  pub fn encipher_stream<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> Result<(), EnigmaError> {
+    // Encipher text line by line without the view, letters pass through the machine
+    // and everything else apart from line breaks is dropped
+
+    // When sending, the indicator is written on its own line ahead of the body
+    if let Some(procedure) = self.config.indicator().filter(|_| !self.config.is_receiving()) {
+      let num_rotors = self.machine.rotors().len();
+      let message_key = match self.config.message_key() {
+        Some(key) => validation::parse_settings(key, "message key", num_rotors)?,
+        None => indicator::random_message_key(num_rotors, &mut self.rng),
+      };
+      let indicator = self.send_indicator(&message_key, procedure)?;
+      writeln!(output, "{}", indicator)?;
+    }
+
+    for line in input.lines() {
+      let line = line?;
+      let mut enciphered = String::new();
+      let mut indicator_letters = 0;
+
+      for c in line.chars().map(|c| c.to_ascii_uppercase()) {
+        if !ALPHABET.contains(&c) {
+          continue;
+        }
+
+        // When receiving, the first letters are the indicator
+        if self.indicator.is_some() {
+          indicator_letters += 1;
+          self.collect_indicator(c)?;
+          continue;
+        }
+
+        self.auto_rotate()?;
+        enciphered.push(self.signal(c)?);
+      }
+
+      if !enciphered.is_empty() || indicator_letters == 0 {
+        writeln!(output, "{}", enciphered)?;
+      }
+    }
+
+    output.flush()?;
+    self.save_state()
+  }
+
+  pub fn encipher_radio<R: Read, W: Write>(&mut self, mut input: R, mut output: W) -> Result<(), EnigmaError> {
+    // Encipher text into a radio message, or with --receive decipher a received radio message
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+
+    if self.config.is_receiving() {
+      let received = RadioMessage::parse(&text, &self.kenngruppen)?;
+
+      // The indicator groups from the header set the message key
+      if self.indicator.is_some() {
+        let indicator = received.indicator().concat();
+        for c in indicator.chars() {
+          self.collect_indicator(c)?;
+        }
+        if self.indicator.is_some() {
+          return Err(EnigmaError::InvalidIndicator(indicator));
+        }
+      }
+
+      let plaintext = self.encipher_letters(received.text())?;
+      writeln!(output, "{}", plaintext)?;
+      output.flush()?;
+      return self.save_state()
+    }
+
+    if let Some(procedure) = self.config.indicator() {
+      let num_rotors = self.machine.rotors().len();
+      let message_key = match self.config.message_key() {
+        Some(key) => validation::parse_settings(key, "message key", num_rotors)?,
+        None => indicator::random_message_key(num_rotors, &mut self.rng),
+      };
+      self.send_indicator(&message_key, procedure)?;
+    }
+
+    let ciphertext = self.encipher_letters(&text)?;
+    let kenngruppe = self.kenngruppe_group();
+    let message = RadioMessage::new(&radio::time_of_origin(), self.sent_indicator.clone(), kenngruppe, &ciphertext);
+    write!(output, "{}", message.format())?;
+    output.flush()?;
+    self.save_state()
+  }
+
+  fn encipher_letters(&mut self, text: &str) -> Result<String, EnigmaError> {
+    // Encipher the letters of TEXT, dropping everything else
+    let mut enciphered = String::new();
+    for c in text.chars().map(|c| c.to_ascii_uppercase()).filter(|c| ALPHABET.contains(c)) {
+      self.auto_rotate()?;
+      enciphered.push(self.signal(c)?);
+    }
+    Ok(enciphered)
+  }
+
+  fn kenngruppe_group(&mut self) -> Option<String> {
+    // Pick one of the day's Kenngruppen for the next message
+    if self.kenngruppen.is_empty() {
+      return None
+    }
+    let kenngruppe = &self.kenngruppen[self.rng.below(self.kenngruppen.len())];
+    Some(radio::kenngruppe_group(kenngruppe, &mut self.rng))
   }
 
-  pub fn wire_plugboard(&mut self) {
-    // Enter pluboard wiring mode
+  pub fn wire_plugboard(&mut self) -> Result<(), EnigmaError> {
+    // Enter pluboard wiring mode, the view is ended even if wiring fails
+    if self.config.is_display()  {self.view.start("front")?};
+    let wired = self.wiring_loop();
+    self.end_view(wired)
+  }
+
+  fn rewire_plugboard(&mut self) -> Result<(), EnigmaError> {
+    // Switch from typing mode to the wiring screen and back again
+    if self.config.is_display()  {self.view.start("front")?};
+    self.wiring_loop()?;
+    if self.config.is_display()  {self.view.start("top")?; self.view.flip(self.config.should_show_instructions())?};
+    Ok(())
+  }
 
-    if self.config.is_display()  {self.view.start("front"); self.view.flip(self.config.should_show_instructions())};
+  fn wiring_loop(&mut self) -> Result<(), EnigmaError> {
+    // Handle key presses until Esc or Enter, Esc puts the plugs back as they were
+
+    let wired = self.machine.plugboard().get_pairs();
+    if self.config.is_display()  {self.view.show_plugs(&wired); self.view.flip(self.config.should_show_instructions())?};
     let mut initial_plug: Option<char> = None;
     
     loop {
@@ -75,22 +251,19 @@ impl EnigmaModel {
           if key_event.kind != KeyEventKind::Press {
             continue;
           }
-
-          if self.plugboard.get_num_connections() == MAX_PLUGS {
-            break;
-          }
           
           match key_event.code {
             KeyCode::Esc => {
-              self.plugboard.reset();
+              self.handle_plugboard_enter(&mut initial_plug)?;
+              self.restore_plugs(&wired)?;
               break;
             },
             KeyCode::Enter => {
-              self.handle_plugboard_enter(&mut initial_plug);
+              self.handle_plugboard_enter(&mut initial_plug)?;
               break;
             }
             KeyCode::Char(c) => {
-              self.handle_plugboard_char(c, &mut initial_plug);
+              self.handle_plugboard_char(c, &mut initial_plug)?;
             },
             _ => {}
           }
@@ -98,177 +271,421 @@ impl EnigmaModel {
       }
     }
 
-    if self.config.is_display()  {self.view.end()};
+    Ok(())
   }
 
 
-  fn handle_plugboard_enter(&mut self, initial_plug: &mut Option<char>) {
-    // Handle Enter KeyCode during wiring phase
-    if let Some(c) = *initial_plug {
+  fn handle_plugboard_enter(&mut self, initial_plug: &mut Option<char>) -> Result<(), EnigmaError> {
+    // Handle Enter KeyCode during wiring phase, a half-made connection is dropped
+    if let Some(c) = initial_plug.take() {
       if self.config.is_display()  {
         self.view.remove_plug(c);
-        self.view.flip(self.config.should_show_instructions());
+        self.view.flip(self.config.should_show_instructions())?;
       };
     }
+    Ok(())
   }
 
-  fn handle_plugboard_char(&mut self, c: char, initial_plug: &mut Option<char>) {
+  fn handle_plugboard_char(&mut self, c: char, initial_plug: &mut Option<char>) -> Result<(), EnigmaError> {
     // Handle characters during wiring phase
     let c = c.to_ascii_uppercase();
-    
-    if self.is_already_plugged(c, *initial_plug) {
-      return
-    }
 
     if !ALPHABET.contains(&c) {
-      return
+      return Ok(())
     }
 
     if self.config.is_debug() {
-      println!("received key press: {}", c);
+      eprintln!("received key press: {}", c);
     }
 
     match *initial_plug {
+      None if self.is_already_plugged(c) => self.unplug(c),
       None => self.start_new_connection(c, initial_plug),
-      Some(ic) => self.complete_connection(ic, c, initial_plug),
+      Some(ic) if ic == c => self.handle_plugboard_enter(initial_plug),
+      Some(ic) => {
+        // Selecting a plugged socket as the other end replaces its connection
+        if self.is_already_plugged(c) {self.unplug(c)?};
+        self.complete_connection(ic, c, initial_plug)
+      }
     }
   }
 
-  fn is_already_plugged(&self, c: char, initial_plug: Option<char>) -> bool {
+  fn is_already_plugged(&self, c: char) -> bool {
     // Check if there is already a plug at that character
-    self.plugboard.get_connections().contains(&Some(c)) || initial_plug == Some(c)
+    self.machine.plugboard().get_connections().contains(&Some(c))
   }
 
-  fn start_new_connection(&mut self, c: char, initial_plug: &mut Option<char>) {
+  fn start_new_connection(&mut self, c: char, initial_plug: &mut Option<char>) -> Result<(), EnigmaError> {
     // Start a new plugboard connection and display
+    if self.machine.plugboard().get_num_connections() == MAX_PLUGS {
+      let status = format!("All {} plugs are in use, select a plug to remove it", MAX_PLUGS);
+      if self.config.is_display()  {self.view.update_status(&status); self.view.flip(self.config.should_show_instructions())?};
+      return Ok(())
+    }
+
     *initial_plug = Some(c);
-    if self.config.is_display()  {self.view.add_initial_plug(c); self.view.flip(self.config.should_show_instructions())};
+    if self.config.is_display()  {self.view.add_initial_plug(c); self.view.flip(self.config.should_show_instructions())?};
+    Ok(())
   }
 
-  fn complete_connection(&mut self, initial_char: char, current_char: char, initial_plug: &mut Option<char>) {
+  fn complete_connection(&mut self, initial_char: char, current_char: char, initial_plug: &mut Option<char>) -> Result<(), EnigmaError> {
     // Complete new plugboard connection and display
-    if self.config.is_display()  {self.view.add_final_plug(current_char, self.plugboard.get_num_connections()); self.view.flip(self.config.should_show_instructions())};
-    if self.config.is_debug() {println!("Added plug connection: {}-{}", initial_char, current_char)};
-    
-    self.plugboard.add_connection(initial_char, current_char);
+    self.machine.plugboard_mut().add_connection(initial_char, current_char)?;
     *initial_plug = None;
-  
+
+    if self.config.is_display()  {self.view.add_final_plug(current_char, self.machine.plugboard().get_num_connections() - 1); self.view.flip(self.config.should_show_instructions())?};
+    if self.config.is_debug() {eprintln!("Added plug connection: {}-{}", initial_char, current_char)};
+    Ok(())
+  }
+
+  fn unplug(&mut self, c: char) -> Result<(), EnigmaError> {
+    // Remove the connection at either end C and renumber the remaining plugs
+    if let Some((c1, c2)) = self.machine.plugboard_mut().remove_connection(c)? {
+      if self.config.is_display()  {
+        self.view.remove_plug(c1);
+        self.view.remove_plug(c2);
+        self.view.show_plugs(&self.machine.plugboard().get_pairs());
+        self.view.update_status("");
+        self.view.flip(self.config.should_show_instructions())?;
+      };
+      if self.config.is_debug() {eprintln!("Removed plug connection: {}-{}", c1, c2)};
+    }
+    Ok(())
+  }
+
+  fn restore_plugs(&mut self, pairs: &[(char, char)]) -> Result<(), EnigmaError> {
+    // Put the plugboard back to the connections in PAIRS
+    if self.config.is_display() {
+      for (c1, c2) in self.machine.plugboard().get_pairs() {
+        self.view.remove_plug(c1);
+        self.view.remove_plug(c2);
+      }
+    }
+
+    self.machine.plugboard_mut().reset();
+    for (c1, c2) in pairs {
+      self.machine.plugboard_mut().add_connection(*c1, *c2)?;
+    }
+
+    if self.config.is_display()  {self.view.show_plugs(pairs); self.view.flip(self.config.should_show_instructions())?};
+    Ok(())
   }
 
-  fn handle_character(&mut self, c: char) {
+  fn handle_character(&mut self, c: char) -> Result<(), EnigmaError> {
     // Handle characters during typing phase
     let c = c.to_ascii_uppercase();
     
     if self.config.is_debug() {
-      println!("received key press: {}", c);
+      eprintln!("received key press: {}", c);
     }
     
     match c {
-      '1' | '2' | '3' => self.manual_rotate(c),
+      c if self.is_rotor_key(c) => self.manual_rotate(c),
+      c if ALPHABET.contains(&c) && self.indicator.is_some() => self.handle_indicator_char(c),
       c if ALPHABET.contains(&c) => {
-        self.auto_rotate();
-        self.key_press(c);
+        // Note the settings the message starts from, for the message log
+        if self.history.is_empty() {self.message_settings = Some(Snapshot::capture(&self.machine))};
+        let steps = self.auto_rotate()?;
+        self.history.push(steps);
+        self.key_press(c)
       }
-      _ => {}
+      _ => Ok(())
     }
   }
 
+  fn is_rotor_key(&self, c: char) -> bool {
+    // Check if the character is the number key of a rotor ('1' = slowest)
+    c.to_digit(10).is_some_and(|n| n >= 1 && n as usize <= self.machine.rotors().len())
+  }
+
   fn get_rotor_letter(&self, rotor_num: usize) -> char {
     // Return the current letter of the specified rotor
-    ALPHABET[self.rotors[rotor_num].get_offset()]
+    ALPHABET[self.machine.rotors()[rotor_num].get_offset()]
   }
 
-  fn key_press(&mut self, mut c: char) {
+  fn key_press(&mut self, c: char) -> Result<(), EnigmaError> {
   // Update the enigma model on keypress
 
     // Update the keyboard view at character C
     if self.config.is_display()  {self.view.update_keyboard(c.to_ascii_lowercase())};
-    if self.config.is_debug() {print!("(IN) {} ", c)};
 
-    // Pass C through the plugboard
-    c = self.plugboard.permutation(c);
-    if self.config.is_debug() {print!("-> [PLUG] -> {} ", c)};
+    // Pass C through the machine
+    let lamp = self.signal(c)?;
 
-    // Pass C through the rotors in the forward direction
-    for i in (0..self.rotors.len()).rev() {
-      c = self.rotors[i].forward_permutation(c);
-      if self.config.is_debug() {print!("-> [R{}] -> {} ", i, c)};
-    }
+    // Update the lamp view at the new character, the transcript keeps the key typed above it
+    self.message.add_typed(c);
+    self.message.add(lamp.to_ascii_uppercase());
+    if !self.config.is_secret() {self.view.update_message_buffer(&self.message.transcript())};
+    if self.config.is_display()  {self.view.update_keyboard(lamp.to_ascii_uppercase()); self.view.flip(self.config.should_show_instructions())?};
+    Ok(())
+  }
 
-    // Apply the reflector to C if present
-    if let Some(reflector) = &self.reflector {
-      c = reflector.forward_permutation(c);
-      if self.config.is_debug() {print!("-> [REFLECT] -> {} ", c)};
+  fn signal(&mut self, c: char) -> Result<char, EnigmaError> {
+  // Pass the signal for character C through the machine and return the lamp character
+    if !self.config.is_debug() {
+      return self.machine.signal(c)
     }
 
-    // Pass C through the rotors in the reverse direction
-    for i in 0..self.rotors.len() as usize {
-      c = self.rotors[i].reverse_permutation(c);
-      if self.config.is_debug() {print!("-> [R{}] -> {} ", i, c)};
-    }
+    eprint!("(IN) {} ", c);
+    let c = self.machine.signal_with(c, |stage, c| eprint!("-> [{}] -> {} ", stage, c));
+    eprintln!("(OUT)");
+    eprintln!("------");
+
+    c
+  }
 
-    // Pass C through the plugboard
-    c = self.plugboard.permutation(c);
-    if self.config.is_debug() {print!("-> [PLUG] -> {} (OUT)", c)};
+  fn manual_rotate(&mut self, rotor_char: char) -> Result<(), EnigmaError> {
+    // Manually rotate the specified rotor
+    let rotor_num = (rotor_char.to_digit(10).unwrap() as usize) - 1;
+    self.rotate(rotor_num)
+  }
 
-    if self.config.is_debug() {
-      println!();
-      println!("------");
+  fn adjust_ring(&mut self, rotor_key: usize) -> Result<(), EnigmaError> {
+    // Move the ring setting of the specified rotor (F1 = slowest) on by one
+    if rotor_key == 0 || rotor_key > self.machine.rotors().len() {
+      return Ok(())
     }
 
-    // Update the lamp view at the new character C
-    self.message.add(c.to_ascii_uppercase());
-    if !self.config.is_secret() {self.view.update_message_buffer(self.message.read())};
-    if self.config.is_display()  {self.view.update_keyboard(c.to_ascii_uppercase()); self.view.flip(self.config.should_show_instructions())};
+    self.machine.rotors_mut()[rotor_key - 1].advance_ring();
 
-  }
+    let rings: Vec<String> = self.machine
+      .rotors()
+      .iter()
+      .map(|rotor| format!("{:02}", rotor.get_ring() + 1))
+      .collect();
+    let status = format!("Rings: {}", rings.join(" "));
 
-  fn manual_rotate(&mut self, rotor_char: char) {
-    // Manually rotate the specified rotor
-    let rotor_num = (rotor_char.to_digit(10).unwrap() as usize) - 1;
-    self.rotate(rotor_num);
+    if self.config.is_debug() {
+      eprintln!("{}", status);
+      eprintln!("------");
+    };
+    if self.config.is_display()  {self.view.update_status(&status); self.view.flip(self.config.should_show_instructions())?};
+    Ok(())
+  }
 
+  fn auto_rotate(&mut self) -> Result<Vec<bool>, EnigmaError> {
+    // Autotmatically rotate rotors on key press, including the double-step
+    let steps = self.machine.step();
+    for i in (0..steps.len()).rev() {
+      if steps[i] {self.show_rotation(i)?};
+    }
+    Ok(steps)
   }
 
-  fn auto_rotate(&mut self) {
-    // Autotmatically rotate rotors on key press
-    for i in (0..self.rotors.len()).rev() {
-      let full_rev = self.rotate(i);
-      if !full_rev {break};
+  fn backspace(&mut self) -> Result<(), EnigmaError> {
+    // Remove the last letter of the message and step the rotors back to where they were
+    if let Some(letters) = self.indicator.as_mut() {
+      letters.pop();
+      self.prompt_indicator();
+      if self.config.is_display()  {self.view.flip(self.config.should_show_instructions())?};
+      return Ok(())
     }
+
+    let Some(steps) = self.history.pop() else {
+      return Ok(())
+    };
+    self.message.pop();
+    self.machine.unstep(&steps);
+    self.set_rotor_windows();
+
+    if self.config.is_debug() {
+      eprintln!("Removed last letter, rotors back to: {}", self.machine.window());
+      eprintln!("------");
+    };
+    if !self.config.is_secret() {self.view.update_message_buffer(&self.message.transcript())};
+    if self.config.is_display()  {
+      self.view.clear_keyboard();
+      if let Some(c) = self.message.last() {self.view.update_keyboard(c)};
+      self.view.flip(self.config.should_show_instructions())?;
+    };
+    Ok(())
+  }
+
+  fn rotate(&mut self, rotor_num: usize) -> Result<(), EnigmaError> {
+    // Rotate the specified rotor and animate in view
+    self.machine.rotate(rotor_num);
+    self.show_rotation(rotor_num)
   }
 
-  fn rotate(&mut self, rotor_num: usize) -> bool {
-    // Rotate the specified rotor and animate in view, return true if full revolution
-    let curr_char = self.get_rotor_letter(rotor_num);
-    let full_rev: bool = self.rotors[rotor_num].advance();
+  fn show_rotation(&mut self, rotor_num: usize) -> Result<(), EnigmaError> {
+    // Animate a rotor that has just moved on by one position
     let next_char = self.get_rotor_letter(rotor_num);
+    let curr_char = ALPHABET[(self.machine.rotors()[rotor_num].get_offset() + ALPHABET.len() - 1) % ALPHABET.len()];
     let rotor_char = char::from_digit((rotor_num + 1) as u32, 10).unwrap();
     
     if self.config.is_display()  {
       if self.config.animate() {
         self.view.rotate_rotor(rotor_char, curr_char, next_char);
-        self.view.flip(self.config.should_show_instructions());
+        self.view.flip(self.config.should_show_instructions())?;
       } else {
         self.view.rotate_rotor_fast(rotor_char, next_char);
-        self.view.flip(self.config.should_show_instructions());
+        self.view.flip(self.config.should_show_instructions())?;
       }
     };
     
     if self.config.is_debug() {
-      println!("Rotor {} turned to: {}", rotor_num, next_char);
-      println!("------");
+      eprintln!("Rotor {} turned to: {}", rotor_num, next_char);
+      eprintln!("------");
+    };
+    Ok(())
+  }
+
+  fn prompt_indicator(&mut self) {
+    // Show the letters of the message key or indicator typed so far on the status line
+    let (Some(procedure), Some(letters)) = (self.config.indicator(), &self.indicator) else {
+      return
+    };
+
+    let num_rotors = self.machine.rotors().len();
+    let status = match self.config.is_receiving() {
+      true => format!("Indicator ({} letters): {}", procedure.indicator_length(num_rotors), letters),
+      false => format!("Message key ({} letters): {}", num_rotors, letters),
+    };
+
+    if self.config.is_debug() {eprintln!("{}", status)};
+    self.view.update_status(&status);
+  }
+
+  fn handle_indicator_char(&mut self, c: char) -> Result<(), EnigmaError> {
+    // Collect the message key (sending) or indicator (receiving) without stepping the rotors
+    let status = match self.collect_indicator(c) {
+      Ok(Some(status)) => status,
+      Ok(None) => {
+        self.prompt_indicator();
+        if self.config.is_display()  {self.view.flip(self.config.should_show_instructions())?};
+        return Ok(())
+      }
+      Err(EnigmaError::InvalidIndicator(indicator)) => {
+        self.indicator = Some(String::new());
+        format!("Indicator {} does not decipher to a message key, type it again", indicator)
+      }
+      Err(err) => return Err(err),
+    };
+
+    self.set_rotor_windows();
+    if !self.config.is_secret() {self.view.update_message_buffer(&self.message.transcript())};
+    if self.config.is_debug() {eprintln!("{}", status)};
+    if self.config.is_display()  {self.view.update_status(&status); self.view.flip(self.config.should_show_instructions())?};
+    Ok(())
+  }
+
+  fn collect_indicator(&mut self, c: char) -> Result<Option<String>, EnigmaError> {
+    // Add C to the indicator letters, once complete set the rotors to the message key
+    // and return a status describing the message key
+    let Some(procedure) = self.config.indicator() else {
+      return Ok(None)
+    };
+    let num_rotors = self.machine.rotors().len();
+    let receiving = self.config.is_receiving();
+    let length = if receiving { procedure.indicator_length(num_rotors) } else { num_rotors };
+
+    let letters = self.indicator.get_or_insert_with(String::new);
+    letters.push(c);
+    if letters.len() < length {
+      return Ok(None)
+    }
+    let letters = self.indicator.take().unwrap_or_default();
+
+    if receiving {
+      let ground = self.machine.positions();
+      let message_key = indicator::decipher_indicator(&mut self.machine, &ground, &letters, procedure)?;
+      self.ground = Some(ground);
+      self.history.clear();
+      let message_key: String = message_key.iter().map(|position| ALPHABET[*position]).collect();
+      return Ok(Some(format!("Message key: {}", message_key)))
+    }
+
+    let message_key = validation::parse_settings(&letters, "message key", num_rotors)?;
+    let indicator = self.send_indicator(&message_key, procedure)?;
+    Ok(Some(format!("Indicator: {} | Message key: {}", indicator, letters)))
+  }
+
+  fn send_indicator(&mut self, message_key: &[usize], procedure: IndicatorProcedure) -> Result<String, EnigmaError> {
+    // Encipher the message key at the current (ground) setting and move the rotors to the message key
+    let ground = self.machine.positions();
+    let indicator = indicator::encipher_indicator(&mut self.machine, &ground, message_key, procedure)?;
+
+    if self.config.is_debug() {
+      eprintln!("Indicator: {} -> rotors set to message key: {}", indicator, self.machine.window());
+      eprintln!("------");
     };
+    // The indicator goes out in the message header, one group per rotor
+    self.sent_indicator = indicator
+      .as_bytes()
+      .chunks(message_key.len())
+      .map(|group| String::from_utf8_lossy(group).into_owned())
+      .collect();
+    self.indicator = None;
+    self.ground = Some(ground);
+    self.history.clear();
+    Ok(indicator)
+  }
 
-    return full_rev
+  fn save_state(&self) -> Result<(), EnigmaError> {
+    // Save the machine state if a state file was given
+    if let Some(path) = self.config.save_state() {
+      Snapshot::capture(&self.machine).save(path)?;
+    }
+    Ok(())
   }
 
-  fn save_and_wipe_message(&mut self) {
-    // Save formated encrypted message to msg.txt
-    self.message.print();
+  fn save_state_now(&mut self) -> Result<(), EnigmaError> {
+    // Save the machine state while typing and report it, or the write error, on the status line
+    let status = match (self.config.save_state(), self.save_state()) {
+      (Some(path), Ok(())) => format!("State saved to {}", path.display()),
+      (Some(path), Err(err)) => format!("Could not save state to {}: {}", path.display(), err),
+      (None, _) => String::from("No state file given (--save-state)"),
+    };
+
+    if self.config.is_debug() {eprintln!("{}", status)};
+    if self.config.is_display()  {self.view.update_status(&status); self.view.flip(self.config.should_show_instructions())?};
+    Ok(())
+  }
+
+  fn save_message(&mut self) -> Result<(), String> {
+    // Write the message to the output file and the log, returning a status line on failure
+    let kenngruppe = self.kenngruppe_group();
+    let mut text = self.message.radio(&self.sent_indicator, kenngruppe.as_deref()).format();
+    if self.config.include_transcript() {
+      text.push_str(&format!("\nTranscript{}\n", self.message.transcript().replace("\r\n", "\n")));
+    }
+
+    let out = self.config.out();
+    message::save(out, &text).map_err(|err| format!("Could not save message to {}: {}", out.display(), err))?;
+
+    if let Some(log) = self.config.log() {
+      let settings = self.message_settings.clone().unwrap_or_else(|| Snapshot::capture(&self.machine));
+      let header = format!("{} | {}", radio::timestamp(), settings.describe());
+      message::append_to_log(log, &header, &text).map_err(|err| format!("Could not append to log {}: {}", log.display(), err))?;
+    }
+    Ok(())
+  }
+
+  fn save_and_wipe_message(&mut self) -> Result<(), EnigmaError> {
+    // Save the encrypted message in radio format, a failed write keeps the message
+    if let Err(status) = self.save_message() {
+      if self.config.is_debug() {eprintln!("{}", status)};
+      if self.config.is_display()  {self.view.update_status(&status); self.view.flip(self.config.should_show_instructions())?};
+      return Ok(())
+    }
+    let status = format!("Message saved to {}", self.config.out().display());
+    self.view.update_status(&status);
+
     self.message.clear();
+    self.message_settings = None;
+    self.sent_indicator.clear();
+    self.history.clear();
     self.view.wipe_message_buffer();
-    self.view.flip(self.config.should_show_instructions());
+
+    // The next message starts with a new indicator at the ground setting
+    if let Some(ground) = self.ground.take() {
+      self.machine.set_positions(&ground);
+      self.indicator = Some(String::new());
+      self.set_rotor_windows();
+      self.prompt_indicator();
+    }
+    if self.config.is_display()  {self.view.flip(self.config.should_show_instructions())?};
+    Ok(())
   }
 
 }
@@ -0,0 +1,95 @@
+use crate::rotor::letters;
+use crate::ALPHABET;
+
+const LETTERS: usize = ALPHABET.len();
+
+// Log probabilities of every run of N letters, counted in a sample of plaintext
+#[derive(Debug, Clone)]
+pub struct NgramModel {
+  n: usize,
+  log_probs: Vec<f64>,
+}
+
+impl NgramModel {
+
+  pub fn from_corpus(corpus: &str, n: usize) -> Self {
+    // Count the N-grams of the letters in CORPUS, ignoring spaces and punctuation
+    // N-grams that never occur get a probability well below the rarest one seen
+    let letters = letters(corpus);
+    let mut counts = vec![0usize; LETTERS.pow(n as u32)];
+    for ngram in letters.windows(n) {
+      counts[index(ngram)] += 1;
+    }
+
+    let total = letters.len().saturating_sub(n - 1).max(1) as f64;
+    let floor = (0.01 / total).log10();
+    let log_probs = counts
+      .iter()
+      .map(|count| if *count == 0 { floor } else { (*count as f64 / total).log10() })
+      .collect();
+
+    NgramModel {
+      n,
+      log_probs,
+    }
+  }
+
+  pub fn n(&self) -> usize {
+    self.n
+  }
+
+  pub fn score(&self, text: &str) -> f64 {
+    // Sum of the log probabilities of the N-grams in TEXT, higher reads more like the corpus
+    self.score_letters(&letters(text))
+  }
+
+  pub fn score_letters(&self, letters: &[u8]) -> f64 {
+    // As `score`, for text given as alphabet positions
+    letters.windows(self.n).map(|ngram| self.log_probs[index(ngram)]).sum()
+  }
+}
+
+pub fn index_of_coincidence(text: &str) -> f64 {
+  // Chance that two letters drawn from TEXT are the same, about 0.076 for German and 0.038 for random letters
+  index_of_coincidence_letters(&letters(text))
+}
+
+pub fn index_of_coincidence_letters(letters: &[u8]) -> f64 {
+  // As `index_of_coincidence`, for text given as alphabet positions
+  let mut counts = [0usize; LETTERS];
+  for letter in letters {
+    counts[*letter as usize] += 1;
+  }
+
+  let pairs = letters.len() * letters.len().saturating_sub(1);
+  match pairs {
+    0 => 0.0,
+    _ => counts.iter().map(|count| count * count.saturating_sub(1)).sum::<usize>() as f64 / pairs as f64,
+  }
+}
+
+fn index(ngram: &[u8]) -> usize {
+  // Position of an N-gram in the table
+  ngram.iter().fold(0, |index, letter| index * LETTERS + *letter as usize)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_index_of_coincidence() {
+    assert_eq!(index_of_coincidence("AAAA"), 1.0);
+    assert_eq!(index_of_coincidence("ABCD"), 0.0);
+    assert_eq!(index_of_coincidence("A"), 0.0);
+  }
+
+  #[test]
+  fn test_score() {
+    let model = NgramModel::from_corpus("Der Wind kommt aus Westen, der Wind dreht.", 3);
+    assert_eq!(model.n(), 3);
+    assert!(model.score("DERWIND") > model.score("QXZJVKP"));
+    assert_eq!(model.score("DE"), 0.0);
+    assert_eq!(model.score_letters(&letters("DERWIND")), model.score("der Wind"));
+  }
+}
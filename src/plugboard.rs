@@ -1,11 +1,21 @@
-use crate::ALPHABET;
+use crate::{ALPHABET, MAX_PLUGS};
+use crate::error::EnigmaError;
+use crate::rotor::letter_index;
+use crate::validation::ValidationError;
 
 const PERM_LEN: usize = ALPHABET.len();
 
 #[derive(Debug)]
 pub struct Plugboard {
   connections: [Option<char>; PERM_LEN],
-  num_connections: usize,
+  wiring: Vec<(char, char)>,
+  table: [u8; PERM_LEN],
+}
+
+impl Default for Plugboard {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl Plugboard {
@@ -13,7 +23,8 @@ impl Plugboard {
   pub fn new() -> Self {
     Plugboard {
       connections: [None; PERM_LEN],
-      num_connections: 0,
+      wiring: Vec::new(),
+      table: std::array::from_fn(|i| i as u8),
     }
   }
 
@@ -21,32 +32,146 @@ impl Plugboard {
     &self.connections
   }
 
+  pub fn get_pairs(&self) -> Vec<(char, char)> {
+    // Return each connection once, in the order they were wired
+    self.wiring.clone()
+  }
+
   pub fn get_num_connections(&self) -> usize {
-    self.num_connections
+    self.wiring.len()
   }
 
-  pub fn add_connection(&mut self, c1: char, c2: char) {
-    let i1 = ALPHABET.iter().position(|l| l == &c1).unwrap();
-    let i2 = ALPHABET.iter().position(|l| l == &c2).unwrap();
+  pub fn add_connection(&mut self, c1: char, c2: char) -> Result<(), EnigmaError> {
+    let i1 = letter_index(c1)?;
+    let i2 = letter_index(c2)?;
+
+    // Each socket takes a single plug
+    for (i, c) in [(i1, c1), (i2, c2)] {
+      if self.connections[i].is_some() || i1 == i2 {
+        return Err(EnigmaError::AlreadyPlugged(c));
+      }
+    }
     
     self.connections[i1] = Some(c2);
     self.connections[i2] = Some(c1);
+    self.table[i1] = i2 as u8;
+    self.table[i2] = i1 as u8;
 
-    self.num_connections += 1;
+    self.wiring.push((c1, c2));
+    Ok(())
     }
 
-  pub fn permutation(&mut self, in_c: char) -> char {
-    let i = ALPHABET.iter().position(|l| l == &in_c).unwrap();
+  pub fn remove_connection(&mut self, c: char) -> Result<Option<(char, char)>, EnigmaError> {
+    // Unplug the connection at either end C and return the removed pair
+    let i = letter_index(c)?;
 
-    match self.connections[i] {
-      Some(out_c) => out_c,
-      None => in_c
-    }
+    let Some(other) = self.connections[i].take() else {
+      return Ok(None)
+    };
+    let j = letter_index(other)?;
+    self.connections[j] = None;
+    self.table[i] = i as u8;
+    self.table[j] = j as u8;
+
+    let position = self.wiring.iter().position(|(c1, c2)| *c1 == c || *c2 == c);
+    Ok(position.map(|position| self.wiring.remove(position)))
+  }
+
+  pub fn permutation(&self, in_c: char) -> Result<char, EnigmaError> {
+    let i = letter_index(in_c)?;
+    Ok(ALPHABET[self.permute_index(i as u8) as usize])
+  }
+
+  pub fn permute_index(&self, index: u8) -> u8 {
+    // Pass the letter at INDEX through the plugboard, unplugged letters come back unchanged
+    self.table[index as usize]
   }
 
   pub fn reset(&mut self) {
     self.connections = [None; PERM_LEN];
-    self.num_connections = 0;
+    self.wiring.clear();
+    self.table = std::array::from_fn(|i| i as u8);
+  }
+
+}
+
+pub fn parse_plugs(value: &str) -> Result<Vec<(char, char)>, ValidationError> {
+  // Parse plugboard connections such as "AV BS CG", every letter can only be plugged once
+  let invalid = || ValidationError::InvalidPlugs(value.to_string());
+  let mut plugged: Vec<char> = Vec::new();
+  let mut pairs: Vec<(char, char)> = Vec::new();
+
+  for pair in value.split(|c: char| c.is_whitespace() || c == ',').filter(|s| !s.is_empty()) {
+    let letters: Vec<char> = pair.chars().map(|c| c.to_ascii_uppercase()).collect();
+
+    if letters.len() != 2 || !letters.iter().all(|c| ALPHABET.contains(c)) {
+      return Err(invalid());
+    }
+
+    if letters[0] == letters[1] || plugged.contains(&letters[0]) || plugged.contains(&letters[1]) {
+      return Err(invalid());
+    }
+
+    plugged.extend(&letters);
+    pairs.push((letters[0], letters[1]));
+  }
+
+  if pairs.len() > MAX_PLUGS {
+    return Err(invalid());
   }
 
-}
\ No newline at end of file
+  Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_parse_plugs() {
+    assert_eq!(parse_plugs("AV bs").unwrap(), vec![('A', 'V'), ('B', 'S')]);
+    assert_eq!(parse_plugs("").unwrap(), vec![]);
+    assert!(parse_plugs("AV AS").is_err());
+    assert!(parse_plugs("AA").is_err());
+    assert!(parse_plugs("AVB").is_err());
+    assert!(parse_plugs("A1").is_err());
+    assert!(parse_plugs("AB CD EF GH IJ KL MN OP QR ST UV").is_err());
+  }
+
+  #[test]
+  fn test_permutation() {
+    let mut plugboard = Plugboard::new();
+    plugboard.add_connection('A', 'V').unwrap();
+
+    assert_eq!(plugboard.permutation('A').unwrap(), 'V');
+    assert_eq!(plugboard.permutation('V').unwrap(), 'A');
+    assert_eq!(plugboard.permutation('B').unwrap(), 'B');
+    assert!(plugboard.permutation('?').is_err());
+    assert!(plugboard.add_connection('V', 'B').is_err());
+    assert!(plugboard.add_connection('C', 'C').is_err());
+  }
+
+  #[test]
+  fn test_remove_connection() {
+    let mut plugboard = Plugboard::new();
+    plugboard.add_connection('A', 'V').unwrap();
+    plugboard.add_connection('B', 'S').unwrap();
+    plugboard.add_connection('C', 'G').unwrap();
+
+    // Either end of the cable unplugs the pair
+    assert_eq!(plugboard.remove_connection('S').unwrap(), Some(('B', 'S')));
+    assert_eq!(plugboard.remove_connection('S').unwrap(), None);
+    assert_eq!(plugboard.get_pairs(), vec![('A', 'V'), ('C', 'G')]);
+    assert_eq!(plugboard.permutation('B').unwrap(), 'B');
+    assert_eq!(plugboard.permute_index(18), 18);
+
+    // The freed sockets can be wired again
+    plugboard.add_connection('B', 'Z').unwrap();
+    assert_eq!(plugboard.get_num_connections(), 3);
+    assert_eq!(plugboard.permutation('Z').unwrap(), 'B');
+    assert_eq!(plugboard.permute_index(1), 25);
+
+    plugboard.reset();
+    assert_eq!(plugboard.permute_index(1), 1);
+  }
+}
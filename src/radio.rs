@@ -0,0 +1,253 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::random::Rng;
+use crate::validation::ValidationError;
+use crate::ALPHABET;
+
+// Letters allowed in a single part of a message, Kenngruppe group included
+pub const PART_LETTERS: usize = 250;
+
+const GROUP_LETTERS: usize = 5;
+const GROUPS_PER_LINE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RadioMessage {
+  time_of_origin: String,
+  indicator: Vec<String>,
+  kenngruppe: Option<String>,
+  text: String,
+}
+
+impl RadioMessage {
+
+  pub fn new(time_of_origin: &str, indicator: Vec<String>, kenngruppe: Option<String>, text: &str) -> Self {
+    // KENNGRUPPE is the full five letter group (two filler letters and the Kenngruppe)
+    RadioMessage {
+      time_of_origin: time_of_origin.to_string(),
+      indicator,
+      kenngruppe,
+      text: text.to_string(),
+    }
+  }
+
+  pub fn time_of_origin(&self) -> &str {
+    &self.time_of_origin
+  }
+
+  pub fn indicator(&self) -> &[String] {
+    &self.indicator
+  }
+
+  pub fn kenngruppe(&self) -> Option<&str> {
+    self.kenngruppe.as_deref()
+  }
+
+  pub fn text(&self) -> &str {
+    &self.text
+  }
+
+  pub fn format(&self) -> String {
+    // Lay the message out as transmitted, split into parts of at most PART_LETTERS letters:
+    //
+    //   1510 = 2TLE 1TL = 250 = PFR MDM =
+    //   XYKGR YUQAH AHTJM ...
+    //
+    // Every part repeats the indicator groups and starts with the Kenngruppe group
+    let kenngruppe = self.kenngruppe.as_deref().unwrap_or("");
+    let letters: Vec<char> = self.text.chars().collect();
+    let chunks: Vec<&[char]> = match letters.is_empty() {
+      true => vec![&letters[..]],
+      false => letters.chunks(PART_LETTERS - kenngruppe.len()).collect(),
+    };
+
+    let indicator = match self.indicator.is_empty() {
+      true => String::new(),
+      false => format!(" {} =", self.indicator.join(" ")),
+    };
+
+    let mut formatted: Vec<String> = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+      let part = match chunks.len() {
+        1 => String::new(),
+        num_parts => format!(" {}TLE {}TL =", num_parts, i + 1),
+      };
+      let body: String = kenngruppe.chars().chain(chunk.iter().copied()).collect();
+
+      formatted.push(format!(
+        "{} ={} {} ={}\n{}",
+        self.time_of_origin,
+        part,
+        body.len(),
+        indicator,
+        group(&body),
+      ));
+    }
+    formatted.join("\n")
+  }
+
+  pub fn parse(text: &str, kenngruppen: &[String]) -> Result<Self, ValidationError> {
+    // Read a message written by `format`, joining its parts back together
+    // The first group of a part is taken as the Kenngruppe group when it ends in one of KENNGRUPPEN
+    let invalid = |reason: &str| ValidationError::InvalidRadioMessage(reason.to_string());
+    let mut parts: Vec<(Vec<String>, String)> = Vec::new();
+
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+      if line.contains('=') {
+        let fields: Vec<String> = line.split('=').map(str::trim).filter(|field| !field.is_empty()).map(String::from).collect();
+        parts.push((fields, String::new()));
+      } else {
+        let (_, body) = parts.last_mut().ok_or_else(|| invalid("the message starts without a header"))?;
+        body.extend(line.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_uppercase()));
+      }
+    }
+
+    if parts.is_empty() {
+      return Err(invalid("no header line"));
+    }
+
+    let mut message = RadioMessage::new("", Vec::new(), None, "");
+    for (i, (fields, body)) in parts.iter().enumerate() {
+      // Header => time of origin, part numbering when split, letter count, indicator groups
+      let mut fields = fields.iter();
+      let time_of_origin = fields.next().ok_or_else(|| invalid("missing time of origin"))?;
+      let mut field = fields.next();
+
+      if let Some(part) = field.filter(|field| field.contains("TL")) {
+        if part.split_whitespace().nth(1) != Some(&format!("{}TL", i + 1)) {
+          return Err(invalid(&format!("part {} is out of order", i + 1)));
+        }
+        field = fields.next();
+      }
+
+      let count: usize = field
+        .and_then(|count| count.parse().ok())
+        .ok_or_else(|| invalid("missing letter count"))?;
+      if count != body.len() {
+        return Err(invalid(&format!("{} letters counted but the header gives {}", body.len(), count)));
+      }
+      if !body.chars().all(|c| ALPHABET.contains(&c)) {
+        return Err(invalid("the body may only contain the letters A-Z"));
+      }
+
+      let indicator: Vec<String> = fields
+        .next()
+        .map(|groups| groups.split_whitespace().map(|group| group.to_ascii_uppercase()).collect())
+        .unwrap_or_default();
+      if !indicator.iter().all(|group| group.chars().all(|c| ALPHABET.contains(&c))) {
+        return Err(invalid("the indicator may only contain the letters A-Z"));
+      }
+
+      // Kenngruppe group
+      let mut body = body.as_str();
+      if body.len() >= GROUP_LETTERS && kenngruppen.iter().any(|kenngruppe| body[..GROUP_LETTERS].ends_with(kenngruppe.as_str())) {
+        message.kenngruppe = Some(body[..GROUP_LETTERS].to_string());
+        body = &body[GROUP_LETTERS..];
+      }
+
+      if i == 0 {
+        message.time_of_origin = time_of_origin.clone();
+        message.indicator = indicator;
+      }
+      message.text.push_str(body);
+    }
+
+    Ok(message)
+  }
+}
+
+pub fn kenngruppe_group(kenngruppe: &str, rng: &mut Rng) -> String {
+  // Pad the three letter Kenngruppe with two random filler letters to a full group
+  let filler: String = (0..2).map(|_| ALPHABET[rng.below(ALPHABET.len())]).collect();
+  format!("{}{}", filler, kenngruppe)
+}
+
+pub fn time_of_origin() -> String {
+  // Current time (UTC) as hours and minutes, e.g. "1510"
+  let seconds = now();
+  format!("{:02}{:02}", seconds / 3600 % 24, seconds / 60 % 60)
+}
+
+pub fn timestamp() -> String {
+  // Current date and time (UTC), e.g. "2026-11-14 15:10 UTC"
+  let seconds = now();
+  let (year, month, day) = civil_date(seconds / 86400);
+  format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, seconds / 3600 % 24, seconds / 60 % 60)
+}
+
+fn now() -> u64 {
+  // Seconds since the Unix epoch
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|time| time.as_secs())
+    .unwrap_or_default()
+}
+
+fn civil_date(days: u64) -> (u64, u64, u64) {
+  // Convert days since 1970-01-01 to year, month and day (proleptic Gregorian calendar)
+  let days = days + 719_468;
+  let era = days / 146_097;
+  let day_of_era = days % 146_097;
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let shifted_month = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+  let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+  let year = era * 400 + year_of_era + u64::from(month <= 2);
+  (year, month, day)
+}
+
+fn group(letters: &str) -> String {
+  // Split LETTERS into five letter groups, GROUPS_PER_LINE to a line
+  let groups: Vec<String> = letters
+    .as_bytes()
+    .chunks(GROUP_LETTERS)
+    .map(|group| String::from_utf8_lossy(group).into_owned())
+    .collect();
+
+  groups
+    .chunks(GROUPS_PER_LINE)
+    .map(|line| line.join(" ") + "\n")
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_format_single_part() {
+    let message = RadioMessage::new("1510", vec!["PFR".to_string(), "MDM".to_string()], Some("XYKGR".to_string()), "YUQAHAHTJMNICYJ");
+    assert_eq!(message.format(), "1510 = 20 = PFR MDM =\nXYKGR YUQAH AHTJM NICYJ\n");
+    assert_eq!(RadioMessage::parse(&message.format(), &["KGR".to_string()]).unwrap(), message);
+  }
+
+  #[test]
+  fn test_format_parts() {
+    let text = "ABCDEFGHIJKLMNOPQRSTUVWXYZ".repeat(20);
+    let message = RadioMessage::new("0915", vec!["QWE".to_string()], Some("ZZKGR".to_string()), &text);
+    let formatted = message.format();
+
+    assert!(formatted.starts_with("0915 = 3TLE 1TL = 250 = QWE =\nZZKGR ABCDE"));
+    assert!(formatted.contains("0915 = 3TLE 3TL = 35 = QWE =\n"));
+    assert_eq!(RadioMessage::parse(&formatted, &["KGR".to_string()]).unwrap(), message);
+  }
+
+  #[test]
+  fn test_civil_date() {
+    assert_eq!(civil_date(0), (1970, 1, 1));
+    assert_eq!(civil_date(20_742), (2026, 10, 16));
+    assert_eq!(civil_date(11_016), (2000, 2, 29));
+  }
+
+  #[test]
+  fn test_parse_invalid() {
+    assert!(RadioMessage::parse("ABCDE FGHIJ", &[]).is_err());
+    assert!(RadioMessage::parse("1510 = 11 =\nABCDE FGHIJ", &[]).is_err());
+    assert!(RadioMessage::parse("1510 = 3TLE 2TL = 5 =\nABCDE", &[]).is_err());
+    assert_eq!(RadioMessage::parse("1510 = 10 =\nabcde fghij", &[]).unwrap().text(), "ABCDEFGHIJ");
+
+    // A garbled indicator group is caught before it reaches the machine
+    assert!(RadioMessage::parse("0622 = 13 = T1H =\nGUUIZ BKBYY XQR", &[]).is_err());
+    assert_eq!(RadioMessage::parse("0622 = 13 = tqh =\nGUUIZ BKBYY XQR", &[]).unwrap().indicator(), &["TQH".to_string()]);
+  }
+}
@@ -0,0 +1,62 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Small seedable random number generator (SplitMix64), good enough for key sheets and searches
+#[derive(Debug, Clone)]
+pub struct Rng {
+  state: u64,
+}
+
+impl Rng {
+
+  pub fn new(seed: u64) -> Self {
+    Rng { state: seed }
+  }
+
+  pub fn from_time() -> Self {
+    // Seed from the system clock when no seed was given
+    let nanos = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|time| time.as_nanos() as u64)
+      .unwrap_or_default();
+    Rng::new(nanos)
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    // Advance the state and mix it into the next output
+    self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = self.state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+  }
+
+  pub fn below(&mut self, n: usize) -> usize {
+    // Return a number in 0..N
+    (self.next_u64() % n as u64) as usize
+  }
+
+  pub fn shuffle<T>(&mut self, items: &mut [T]) {
+    // Shuffle ITEMS in place (Fisher-Yates)
+    for i in (1..items.len()).rev() {
+      items.swap(i, self.below(i + 1));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_seeded_sequence() {
+    let first: Vec<usize> = (0..20).scan(Rng::new(14), |rng, _| Some(rng.below(26))).collect();
+    let second: Vec<usize> = (0..20).scan(Rng::new(14), |rng, _| Some(rng.below(26))).collect();
+    assert_eq!(first, second);
+    assert!(first.iter().all(|n| *n < 26));
+
+    let mut letters: Vec<usize> = (0..26).collect();
+    Rng::new(14).shuffle(&mut letters);
+    letters.sort();
+    assert_eq!(letters, (0..26).collect::<Vec<usize>>());
+  }
+}
@@ -1,108 +1,193 @@
 use crate::ALPHABET;
+use crate::error::EnigmaError;
+use crate::validation;
 
 const PERM_LEN: usize = ALPHABET.len();
+const PAWNS: usize = 3;
+
+// Wiring of a rotor for every shift of its core against the contacts, by letter index
+type Tables = [[u8; PERM_LEN]; PERM_LEN];
 
 #[derive(Debug)]
 pub struct Rotor {
-  // name: String,
-  permutation: [char; PERM_LEN],
+  name: String,
+  forward: Box<Tables>,
+  reverse: Box<Tables>,
   offset: usize,
-  // notches: Vec<usize>,
+  ring: usize,
+  shift: usize,
+  notches: [bool; PERM_LEN],
   length: usize,
 }
 
 impl Rotor {
 
-  pub fn new(perm_str: &str) -> Self {
+  pub fn new(name: &str, perm_str: &str, notch_chars: Option<Vec<char>>) -> Result<Self, EnigmaError> {
 
-  // pub fn new(name: &str, perm_str: &str, notch_chars: Option<Vec<char>>) -> Self {
+    let name = String::from(name);
 
-    // let name = String::from(name);
-    
-    let permutation: [char; 26] = perm_str.chars()
-                                          .map(|c| c.to_ascii_uppercase())
-                                          .collect::<Vec<char>>()
-                                          .try_into()
-                                          .unwrap();
-    
-    // let notch_chars: Option<Vec<char>> = Some(vec![permutation[15]]);
-
-    // let notches: Vec<usize> = match notch_chars {
-    //   Some(notch_chars) => notch_chars
-    //     .iter()
-    //     .map(|notch_c| 
-    //       ALPHABET
-    //       .iter()
-    //       .position(|c| c == notch_c)
-    //       .unwrap())
-    //     .collect(),
-    //   None => vec![0],
-    // };
+    // The wiring must contain every letter exactly once
+    let perm_str = perm_str.to_ascii_uppercase();
+    validation::validate_permutation(&perm_str, &name)?;
+
+    let mut wiring = [0u8; PERM_LEN];
+    for (slot, c) in wiring.iter_mut().zip(perm_str.chars()) {
+      *slot = letter_index(c)? as u8;
+    }
+
+    // Work the wiring out ahead for every shift so a letter passes with a single lookup
+    let mut forward: Box<Tables> = Box::new([[0; PERM_LEN]; PERM_LEN]);
+    let mut reverse: Box<Tables> = Box::new([[0; PERM_LEN]; PERM_LEN]);
+    for shift in 0..PERM_LEN {
+      for letter in 0..PERM_LEN {
+        let wire = (wiring[(letter + shift) % PERM_LEN] as usize + PERM_LEN - shift) % PERM_LEN;
+        forward[shift][letter] = wire as u8;
+        reverse[shift][wire] = letter as u8;
+      }
+    }
+
+    // Notches are given as the window letter shown when the turnover pawn engages
+    let mut notches = [false; PERM_LEN];
+    for notch_c in notch_chars.unwrap_or_default() {
+      notches[letter_index(notch_c.to_ascii_uppercase())?] = true;
+    }
     
-    Rotor {
-      // name,
-      permutation,
+    Ok(Rotor {
+      name,
+      forward,
+      reverse,
       offset: 0,
-      // notches,
+      ring: 0,
+      shift: 0,
+      notches,
       length: PERM_LEN,
-    }
+    })
   }
   
+  pub fn get_name(&self) -> &str {
+    // Return the catalogue name of the rotor
+    &self.name
+  }
+
   pub fn get_offset(&self) -> usize {
     // Return the current offset
     self.offset
   }
 
+  pub fn set_offset(&mut self, offset: usize) {
+    // Set the offset (the letter shown in the window), wrapping around when past the end
+    self.offset = offset % self.length;
+    self.update_shift();
+  }
+
+  pub fn get_ring(&self) -> usize {
+    // Return the current ring setting
+    self.ring
+  }
+
+  pub fn set_ring(&mut self, ring: usize) {
+    // Set the ring setting, wrapping around when past the end
+    self.ring = ring % self.length;
+    self.update_shift();
+  }
+
+  pub fn advance_ring(&mut self) {
+    // Move the ring setting to the next position, wrapping around when at the end
+    self.ring = (self.ring + 1) % self.length;
+    self.update_shift();
+  }
+
+  pub fn is_at_notch(&self) -> bool {
+    // Return true if the current position lets the pawn engage a notch
+    self.notches[self.offset]
+  }
+
   pub fn advance(&mut self) -> bool {
     // Move the offset to the next position, wrapping around when at the end
-    self.offset = (self.offset + 1) % self.length;
-    // self.notches.iter().any(|notch| notch == &self.offset)
-    self.offset == 0
+    // Returns true if the rotor turned over from a notch position
+    let turnover = self.is_at_notch();
+    self.offset = if self.offset + 1 == self.length { 0 } else { self.offset + 1 };
+    self.update_shift();
+    turnover
+  }
+
+  pub fn retreat(&mut self) {
+    // Move the offset back to the previous position, wrapping around when at the start
+    self.offset = if self.offset == 0 { self.length - 1 } else { self.offset - 1 };
+    self.update_shift();
+  }
+
+  fn update_shift(&mut self) {
+    // The ring setting shifts the wiring against the letter ring (and notch)
+    // Kept up to date whenever the offset or ring moves, so a lookup does no arithmetic
+    self.shift = match self.offset >= self.ring {
+      true => self.offset - self.ring,
+      false => self.offset + self.length - self.ring,
+    };
   }
 
-  pub fn forward_permutation(&self, input_char: char) -> char {
+  pub fn forward_index(&self, index: u8) -> u8 {
+    // Pass the letter at INDEX through the rotor in the forward direction
+    self.forward[self.shift][index as usize]
+  }
+
+  pub fn reverse_index(&self, index: u8) -> u8 {
+    // Pass the letter at INDEX through the rotor in the reverse direction
+    self.reverse[self.shift][index as usize]
+  }
+
+  pub fn forward_permutation(&self, input_char: char) -> Result<char, EnigmaError> {
     // Apply the rotors permutation in the forward direction
-    self.apply_permutation(
-      input_char, 
-      &ALPHABET, 
-      &self.permutation)
+    let index = letter_index(input_char)? as u8;
+    Ok(ALPHABET[self.forward_index(index) as usize])
   }
 
-  pub fn reverse_permutation(&self, input_char: char) -> char {
+  pub fn reverse_permutation(&self, input_char: char) -> Result<char, EnigmaError> {
     // Apply the rotors permutation in the reverse direction
-    self.apply_permutation(
-      input_char, 
-      &self.permutation, 
-      &ALPHABET)
+    let index = letter_index(input_char)? as u8;
+    Ok(ALPHABET[self.reverse_index(index) as usize])
   }
+}
 
-  pub fn apply_permutation(&self, input_char: char, input_seq: &[char; PERM_LEN], output_seq: &[char; PERM_LEN]) -> char {
-    // Apply the permutation
+pub fn letter_index(c: char) -> Result<usize, EnigmaError> {
+  // Find the position of C in the alphabet
+  match c {
+    'A'..='Z' => Ok(c as usize - 'A' as usize),
+    _ => Err(EnigmaError::InvalidCharacter(c)),
+  }
+}
 
-    // Find position of input character in the alphabet
-    let in_index = ALPHABET.iter()
-                                .position(|l| l == &input_char)
-                                .unwrap();
-    
-    // Apply offset and wrap around
-    let perm_index = (in_index + self.offset) % self.length;
-    
-    // Get character from output sequence
-    let perm_char = output_seq[perm_index];
-    
-    // Find position of this character in input sequence
-    let out_index = input_seq.iter()
-                                .position(|l| l == &perm_char)
-                                .unwrap();
-
-    // Wraparound logic for negative values
-    let mut final_index = out_index as isize - self.offset as isize;
-    if final_index < 0 {
-      final_index += self.length as isize;  // If negative, wrap around
-    }
+pub fn letters(text: &str) -> Vec<u8> {
+  // Alphabet positions of the letters in TEXT, anything that is not a-z or A-Z is skipped
+  text
+    .bytes()
+    .filter(u8::is_ascii_alphabetic)
+    .map(|b| b.to_ascii_uppercase() - b'A')
+    .collect()
+}
+
+pub fn text(letters: &[u8]) -> String {
+  // Letters for the alphabet positions in LETTERS
+  letters.iter().map(|letter| ALPHABET[*letter as usize]).collect()
+}
 
-    // Return the final character from alphabet
-    ALPHABET[final_index as usize]
+pub fn turn(rotors: &mut [Rotor], steps: &mut [bool]) {
+  // Step ROTORS (slowest first) for a key press and record in STEPS which rotors moved
+  // The rightmost rotor always steps, and a pawn that drops into the notch of
+  // rotor i+1 pushes both rotor i and rotor i+1 - the double-step anomaly
+  // There are only PAWNS pawns, so a fourth (M4 Greek) wheel never steps
+  // Going right to left each notch is read before its rotor moves and carries to the next rotor
+  let first = rotors.len().saturating_sub(PAWNS);
+  steps[..first].fill(false);
+
+  let mut carry = true;
+  for i in (first..rotors.len()).rev() {
+    let notch = rotors[i].is_at_notch();
+    steps[i] = carry || (notch && i > first);
+    if steps[i] {
+      rotors[i].advance();
+    }
+    carry = notch;
   }
 }
 
@@ -112,53 +197,142 @@ mod tests {
 
   #[test]
   fn test_advance() {
-    let mut rotor = Rotor::new("EKMFLGDQVZNTOWYHXUSPAIBRCJ");
+    let mut rotor = Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap();
     assert_eq!(rotor.offset, 0);
     
     // Test advancing and getting false (not a full rotation)
-    assert_eq!(rotor.advance(), false);
+    assert!(!rotor.advance());
     assert_eq!(rotor.offset, 1);
     
-    // Set to one before notch
-    rotor.offset = 25;
+    // Set to the notch (Q)
+    rotor.offset = 16;
     
-    // Test advancing and getting true (full rotation)
-    assert_eq!(rotor.advance(), true);
+    // Test advancing and getting true (turnover)
+    assert!(rotor.advance());
+    assert_eq!(rotor.offset, 17);
+
+    // Test wrapping around without a turnover
+    rotor.offset = 25;
+    assert!(!rotor.advance());
     assert_eq!(rotor.offset, 0);
   }
 
+  #[test]
+  fn test_invalid_input() {
+    assert!(Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRC", None).is_err());
+    assert!(Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCC", None).is_err());
+    assert!(Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['1'])).is_err());
+
+    let rotor = Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", None).unwrap();
+    assert!(rotor.forward_permutation('a').is_err());
+  }
+
+  #[test]
+  fn test_ring_setting() {
+    let mut rotor = Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap();
+    
+    // Ring setting B shifts the wiring one place
+    rotor.set_ring(1);
+    assert_eq!(rotor.forward_permutation('A').unwrap(), 'K');
+    assert_eq!(rotor.reverse_permutation('K').unwrap(), 'A');
+
+    // Ring and offset moved together cancel out
+    rotor.advance();
+    assert_eq!(rotor.forward_permutation('A').unwrap(), 'E');
+
+    // The notch follows the letter ring, not the wiring
+    rotor.offset = 16;
+    assert!(rotor.is_at_notch());
+  }
+
+  #[test]
+  fn test_double_stepping() {
+    let mut rotors = vec![
+      Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap(),
+      Rotor::new("II", "AJDKSIRUXBLHWTMCQGZNPYFVOE", Some(vec!['E'])).unwrap(),
+      Rotor::new("III", "BDFHJLCPRTXVZNYEIWGAKMUSQO", Some(vec!['V'])).unwrap(),
+    ];
+
+    // Start at ADU, expected sequence ADV -> AEW -> BFX -> BFY
+    rotors[0].offset = 0;
+    rotors[1].offset = 3;
+    rotors[2].offset = 20;
+
+    let expected = ["ADV", "AEW", "BFX", "BFY"];
+    let mut steps = [false; 3];
+    for window in expected {
+      turn(&mut rotors, &mut steps);
+      let letters: String = rotors.iter().map(|r| ALPHABET[r.offset]).collect();
+      assert_eq!(letters, window);
+    }
+  }
+
+  #[test]
+  fn test_index_tables() {
+    let mut rotor = Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap();
+    rotor.set_ring(5);
+    for offset in 0..26 {
+      rotor.set_offset(offset);
+      for index in 0..26 {
+        assert_eq!(rotor.reverse_index(rotor.forward_index(index)), index);
+      }
+    }
+
+    assert_eq!(letters("Wetter, 1940!"), vec![22, 4, 19, 19, 4, 17]);
+    assert_eq!(text(&letters("wetter")), "WETTER");
+  }
+
+  #[test]
+  fn test_greek_wheel_never_steps() {
+    let mut rotors = vec![
+      Rotor::new("Beta", "LEYJVCNIXWPBQMDRTAKZGFUHOS", None).unwrap(),
+      Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap(),
+      Rotor::new("II", "AJDKSIRUXBLHWTMCQGZNPYFVOE", Some(vec!['E'])).unwrap(),
+      Rotor::new("III", "BDFHJLCPRTXVZNYEIWGAKMUSQO", Some(vec!['V'])).unwrap(),
+    ];
+
+    // With every pawn engaged, all but the Greek wheel step
+    rotors[1].offset = 16;
+    rotors[2].offset = 4;
+    rotors[3].offset = 21;
+    let mut steps = [true; 4];
+    turn(&mut rotors, &mut steps);
+    assert_eq!(steps, [false, true, true, true]);
+    assert_eq!(rotors[0].offset, 0);
+  }
+
   #[test]
     fn test_forward_permutation() {
-      let rotor = Rotor::new("EKMFLGDQVZNTOWYHXUSPAIBRCJ");      
-      assert_eq!(rotor.forward_permutation('A'), 'E');
-      assert_eq!(rotor.forward_permutation('Z'), 'J');
+      let rotor = Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap();      
+      assert_eq!(rotor.forward_permutation('A').unwrap(), 'E');
+      assert_eq!(rotor.forward_permutation('Z').unwrap(), 'J');
     }
 
     #[test]
     fn test_reverse_permutation() {
-      let rotor = Rotor::new("EKMFLGDQVZNTOWYHXUSPAIBRCJ");      
-      assert_eq!(rotor.reverse_permutation('K'), 'B');
-      assert_eq!(rotor.reverse_permutation('C'), 'Y');
+      let rotor = Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap();      
+      assert_eq!(rotor.reverse_permutation('K').unwrap(), 'B');
+      assert_eq!(rotor.reverse_permutation('C').unwrap(), 'Y');
     }
 
     #[test]
     fn test_rotation_affects_permutation() {
-      let mut rotor = Rotor::new("EKMFLGDQVZNTOWYHXUSPAIBRCJ");      
+      let mut rotor = Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap();      
       // At offset 0
-      let initial_mapping = rotor.forward_permutation('A');
+      let initial_mapping = rotor.forward_permutation('A').unwrap();
       
       // Advance the rotor
       rotor.advance();
       
       // At offset 1, the mapping should be different
-      let new_mapping = rotor.forward_permutation('A');
+      let new_mapping = rotor.forward_permutation('A').unwrap();
       assert_ne!(initial_mapping, new_mapping);
     }
     
     #[test]
     fn test_full_rotation_cycle() {
-      let mut rotor = Rotor::new("EKMFLGDQVZNTOWYHXUSPAIBRCJ");      
-      let initial_mapping = rotor.forward_permutation('A');
+      let mut rotor = Rotor::new("I", "EKMFLGDQVZNTOWYHXUSPAIBRCJ", Some(vec!['Q'])).unwrap();      
+      let initial_mapping = rotor.forward_permutation('A').unwrap();
       
       // Rotate through a full cycle (26 positions)
       for _ in 0..26 {
@@ -166,7 +340,7 @@ mod tests {
       }
       
       // After a full rotation, the mapping should be the same
-      let final_mapping = rotor.forward_permutation('A');
+      let final_mapping = rotor.forward_permutation('A').unwrap();
       assert_eq!(initial_mapping, final_mapping);
     }
 }
\ No newline at end of file
@@ -0,0 +1,264 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::catalogue::Catalogue;
+use crate::error::EnigmaError;
+use crate::machine::Machine;
+use crate::plugboard::Plugboard;
+use crate::rotor;
+use crate::ALPHABET;
+
+const LETTERS: usize = ALPHABET.len();
+
+// How often progress is reported while the workers run
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+// Start positions scored by a search, with the wheel order they were found on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+  score: f64,
+  wheel_order: Vec<String>,
+  positions: Vec<usize>,
+}
+
+impl Candidate {
+
+  pub fn score(&self) -> f64 {
+    self.score
+  }
+
+  pub fn wheel_order(&self) -> &[String] {
+    &self.wheel_order
+  }
+
+  pub fn positions(&self) -> &[usize] {
+    &self.positions
+  }
+}
+
+// Every start position of a set of wheel orders, split over worker threads
+//
+// The keyspace is cut into chunks of one wheel order and one position of the slowest rotor,
+// workers take the next chunk as they finish and each deciphers on its own Machine
+pub struct Search<'a> {
+  catalogue: &'a Catalogue,
+  wheel_orders: Vec<Vec<String>>,
+  reflector: String,
+  rings: Vec<usize>,
+  threads: usize,
+}
+
+impl<'a> Search<'a> {
+
+  pub fn new(catalogue: &'a Catalogue, wheel_orders: Vec<Vec<String>>, reflector: &str, rings: &[usize]) -> Self {
+    // Rings are left at 01 when not given, the plugboard is always empty
+    // Uses one worker per CPU core
+    Search {
+      catalogue,
+      wheel_orders,
+      reflector: reflector.to_string(),
+      rings: rings.to_vec(),
+      threads: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+    }
+  }
+
+  pub fn set_threads(&mut self, threads: usize) {
+    // Set the number of worker threads, at least one
+    self.threads = threads.max(1);
+  }
+
+  pub fn size(&self) -> usize {
+    // Number of wheel orders and start positions searched
+    self.wheel_orders.iter().map(|order| LETTERS.pow(order.len() as u32)).sum()
+  }
+
+  pub fn best<S, P>(&self, ciphertext: &str, keep: usize, score: S, progress: P) -> Result<Vec<Candidate>, EnigmaError>
+  where
+    S: Fn(&[u8]) -> f64 + Sync,
+    P: FnMut(usize, usize),
+  {
+    // Decipher from every start position and return the KEEP with the highest SCORE, best first
+    // SCORE is given the deciphered letters as alphabet positions
+    let ciphertext = rotor::letters(ciphertext);
+    let chunks = self.split(progress, |machine, wheel_order, positions| {
+      let mut best: Vec<Candidate> = Vec::new();
+      for positions in positions {
+        machine.set_positions(&positions);
+        let score = score(&machine.encipher_letters(&ciphertext));
+        if best.len() < keep || best.last().is_some_and(|worst| score > worst.score) {
+          best.push(Candidate { score, wheel_order: wheel_order.to_vec(), positions });
+          best.sort_by(|a, b| b.score.total_cmp(&a.score));
+          best.truncate(keep);
+        }
+      }
+      Ok(best)
+    })?;
+
+    let mut best: Vec<Candidate> = chunks.into_iter().flatten().collect();
+    best.sort_by(|a, b| b.score.total_cmp(&a.score));
+    best.truncate(keep);
+    Ok(best)
+  }
+
+  pub fn run<T, S, I, V, P>(&self, init: I, visit: V, progress: P) -> Result<Vec<T>, EnigmaError>
+  where
+    T: Send,
+    I: Fn(&Machine) -> Result<S, EnigmaError> + Sync,
+    V: Fn(&mut S, &mut Machine, &[usize]) -> Result<Option<T>, EnigmaError> + Sync,
+    P: FnMut(usize, usize),
+  {
+    // Call VISIT at every start position and collect what it returns
+    // INIT sets up state for each chunk from its machine, e.g. tables for the wheel order
+    let chunks = self.split(progress, |machine, _, positions| {
+      let mut state = init(machine)?;
+      let mut found = Vec::new();
+      for positions in positions {
+        machine.set_positions(&positions);
+        found.extend(visit(&mut state, machine, &positions)?);
+      }
+      Ok(found)
+    })?;
+
+    Ok(chunks.into_iter().flatten().collect())
+  }
+
+  fn split<R, W, P>(&self, mut progress: P, work: W) -> Result<Vec<R>, EnigmaError>
+  where
+    R: Send,
+    W: Fn(&mut Machine, &[String], Box<dyn Iterator<Item = Vec<usize>>>) -> Result<R, EnigmaError> + Sync,
+    P: FnMut(usize, usize),
+  {
+    // Hand the chunks out to the workers and report PROGRESS (positions done, positions in all)
+    // from the calling thread, results come back in chunk order
+    let chunks: Vec<(usize, usize)> = (0..self.wheel_orders.len())
+      .flat_map(|order| (0..LETTERS).map(move |slowest| (order, slowest)))
+      .collect();
+    let total = self.size();
+
+    let next = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let running = AtomicUsize::new(self.threads.min(chunks.len()));
+    let results: Mutex<Vec<(usize, R)>> = Mutex::new(Vec::new());
+    let error: Mutex<Option<EnigmaError>> = Mutex::new(None);
+
+    // Workers wake the calling thread as they finish, so it does not sleep out the interval
+    let caller = thread::current();
+    thread::scope(|scope| {
+      let workers: Vec<_> = (0..self.threads.min(chunks.len()))
+        .map(|_| scope.spawn(|| {
+          while !failed.load(Ordering::Relaxed) {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(&(order, slowest)) = chunks.get(i) else {
+              break;
+            };
+
+            let wheel_order = &self.wheel_orders[order];
+            let num_rotors = wheel_order.len();
+            let result = self.machine(wheel_order).and_then(|mut machine| {
+              let positions = (0..LETTERS.pow(num_rotors as u32 - 1)).map(move |n| {
+                let mut positions = vec![slowest];
+                positions.extend((1..num_rotors).map(|i| n / LETTERS.pow((num_rotors - 1 - i) as u32) % LETTERS));
+                positions
+              });
+              work(&mut machine, wheel_order, Box::new(positions))
+            });
+
+            match result {
+              Ok(result) => results.lock().unwrap().push((i, result)),
+              Err(err) => {
+                failed.store(true, Ordering::Relaxed);
+                error.lock().unwrap().get_or_insert(err);
+              }
+            }
+            done.fetch_add(LETTERS.pow(num_rotors as u32 - 1), Ordering::Relaxed);
+          }
+          running.fetch_sub(1, Ordering::Release);
+          caller.unpark();
+        }))
+        .collect();
+
+      // Report each change, the last report once every worker has finished
+      let mut reported = None;
+      loop {
+        // A worker that panicked never counts itself out, but does finish
+        let finished = running.load(Ordering::Acquire) == 0 || workers.iter().all(|worker| worker.is_finished());
+        let positions = done.load(Ordering::Relaxed);
+        if reported != Some(positions) {
+          progress(positions, total);
+          reported = Some(positions);
+        }
+        if finished {
+          break;
+        }
+        thread::park_timeout(PROGRESS_INTERVAL);
+      }
+    });
+
+    if let Some(err) = error.into_inner().unwrap() {
+      return Err(err);
+    }
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(i, _)| *i);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+  }
+
+  pub fn machine(&self, wheel_order: &[String]) -> Result<Machine, EnigmaError> {
+    // A machine with the wheel order and rings of the search and no plugs
+    let rotors = self.catalogue.wheel_order(wheel_order)?;
+    let reflector = self.catalogue.reflector(&self.reflector, rotors.len() == 4)?;
+    let mut machine = Machine::new(rotors, Some(reflector), Plugboard::new());
+    machine.set_rings(&self.rings);
+    Ok(machine)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::loader;
+
+  fn catalogue() -> Catalogue {
+    Catalogue::from_yaml(&loader::permutations_yaml(&[]).unwrap())
+  }
+
+  fn order(names: &[&str]) -> Vec<String> {
+    names.iter().map(|name| name.to_string()).collect()
+  }
+
+  #[test]
+  fn test_run_visits_every_position() {
+    let catalogue = catalogue();
+    let mut search = Search::new(&catalogue, vec![order(&["I", "II", "III"]), order(&["III", "II", "I"])], "B", &[]);
+    search.set_threads(3);
+    assert_eq!(search.size(), 2 * 17_576);
+
+    let mut reported = 0;
+    let found = search.run(
+      |_| Ok(()),
+      |_, machine, positions| Ok((machine.window() == "QEV").then(|| positions.to_vec())),
+      |done, total| reported = done.max(reported).min(total),
+    ).unwrap();
+
+    assert_eq!(found, vec![vec![16, 4, 21], vec![16, 4, 21]]);
+    assert_eq!(reported, 2 * 17_576);
+  }
+
+  #[test]
+  fn test_best() {
+    let catalogue = catalogue();
+    let mut machine = Search::new(&catalogue, Vec::new(), "B", &[]).machine(&order(&["II", "I", "III"])).unwrap();
+    machine.set_positions(&[3, 7, 11]);
+    let ciphertext = machine.encipher_str("AAAAAAAAAAAAAAAAAAAA").unwrap();
+
+    // Counting the letter A gives the start position full marks
+    let search = Search::new(&catalogue, vec![order(&["II", "I", "III"])], "B", &[]);
+    let best = search.best(&ciphertext, 3, |plaintext| plaintext.iter().filter(|letter| **letter == 0).count() as f64, |_, _| {}).unwrap();
+    assert_eq!(best.len(), 3);
+    assert_eq!(best[0].positions(), &[3, 7, 11]);
+    assert_eq!(best[0].score(), 20.0);
+    assert!(best[1].score() < 20.0);
+  }
+}
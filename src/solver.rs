@@ -0,0 +1,232 @@
+use crate::error::EnigmaError;
+use crate::machine::Machine;
+use crate::ngram::{index_of_coincidence_letters, NgramModel};
+use crate::plugboard::Plugboard;
+use crate::rotor;
+use crate::search::Search;
+use crate::{ALPHABET, MAX_PLUGS};
+
+const LETTERS: usize = ALPHABET.len();
+
+// Settings recovered from the ciphertext and the n-gram score of the plaintext they give
+#[derive(Debug, Clone)]
+pub struct Solution {
+  wheel_order: Vec<String>,
+  rings: Vec<usize>,
+  positions: Vec<usize>,
+  plugs: Vec<(char, char)>,
+  score: f64,
+  plaintext: String,
+}
+
+impl Solution {
+
+  pub fn wheel_order(&self) -> &[String] {
+    &self.wheel_order
+  }
+
+  pub fn rings(&self) -> &[usize] {
+    &self.rings
+  }
+
+  pub fn positions(&self) -> &[usize] {
+    &self.positions
+  }
+
+  pub fn window(&self) -> String {
+    // Letters shown in the rotor windows at the start of the message
+    self.positions.iter().map(|position| ALPHABET[*position]).collect()
+  }
+
+  pub fn plugs(&self) -> &[(char, char)] {
+    &self.plugs
+  }
+
+  pub fn score(&self) -> f64 {
+    // N-gram score per letter of the plaintext
+    self.score / self.plaintext.len().max(1) as f64
+  }
+
+  pub fn plaintext(&self) -> &str {
+    &self.plaintext
+  }
+}
+
+pub fn solve<P: FnMut(usize, usize)>(
+  search: &Search,
+  ciphertext: &str,
+  model: &NgramModel,
+  keep: usize,
+  progress: P,
+) -> Result<Vec<Solution>, EnigmaError> {
+  // Recover the key from the ciphertext alone, in three stages:
+  //   1. wheel order and start positions with the rings at 01 and no plugs, by index of coincidence
+  //   2. ring settings of the KEEP best of those, again by index of coincidence
+  //   3. plugboard pairs, hill climbing on the n-gram score
+  // Every stage deciphers on the simulator's own Machine, so the settings found work on it unchanged
+  // The first stage is split over the search's workers and reports PROGRESS as it goes
+  let candidates = search.best(ciphertext, keep, index_of_coincidence_letters, progress)?;
+
+  let mut solutions = Vec::new();
+  for candidate in candidates {
+    let mut machine = search.machine(candidate.wheel_order())?;
+    let (rings, positions, _) = search_rings(&mut machine, ciphertext, candidate.positions())?;
+    let (plugs, score) = climb_plugboard(&mut machine, ciphertext, &positions, model)?;
+
+    machine.set_positions(&positions);
+    let plaintext = machine.encipher_str(ciphertext)?;
+    solutions.push(Solution { wheel_order: candidate.wheel_order().to_vec(), rings, positions, plugs, score, plaintext });
+  }
+
+  solutions.sort_by(|a, b| b.score().total_cmp(&a.score()));
+  Ok(solutions)
+}
+
+pub fn search_rings(machine: &mut Machine, ciphertext: &str, positions: &[usize]) -> Result<(Vec<usize>, Vec<usize>, f64), EnigmaError> {
+  // Find the rings of the fast then the middle rotor, starting from POSITIONS found with the rings at 01
+  // Turning a ring and its rotor together keeps the wiring where it was and only moves the turnover,
+  // the slowest rotor drives nothing so its ring is left at 01
+  // Returns the rings, the positions that go with them and their index of coincidence
+  let ciphertext = rotor::letters(ciphertext);
+  let num_rotors = positions.len();
+  let mut rings = vec![0; num_rotors];
+  let mut positions = positions.to_vec();
+  machine.set_rings(&rings);
+  machine.set_positions(&positions);
+  let mut best = index_of_coincidence_letters(&machine.encipher_letters(&ciphertext));
+
+  for rotor in (1..num_rotors).rev().take(2) {
+    let (mut best_ring, start) = (0, positions[rotor]);
+    for ring in 1..LETTERS {
+      rings[rotor] = ring;
+      positions[rotor] = (start + ring) % LETTERS;
+      machine.set_rings(&rings);
+      machine.set_positions(&positions);
+
+      let ioc = index_of_coincidence_letters(&machine.encipher_letters(&ciphertext));
+      if ioc > best {
+        (best, best_ring) = (ioc, ring);
+      }
+    }
+    rings[rotor] = best_ring;
+    positions[rotor] = (start + best_ring) % LETTERS;
+  }
+
+  machine.set_rings(&rings);
+  Ok((rings, positions, best))
+}
+
+pub fn climb_plugboard(machine: &mut Machine, ciphertext: &str, positions: &[usize], model: &NgramModel) -> Result<(Vec<(char, char)>, f64), EnigmaError> {
+  // Hill climb from an empty plugboard, trying every pair of letters each round
+  // A pair already plugged is taken out, otherwise it is plugged in place of any cables on its letters
+  // The best change is kept until none improves the n-gram score, the machine is left with the plugs found
+  let ciphertext = rotor::letters(ciphertext);
+  let mut score = |plugs: &[(char, char)]| -> Result<f64, EnigmaError> {
+    *machine.plugboard_mut() = plugboard(plugs)?;
+    machine.set_positions(positions);
+    Ok(model.score_letters(&machine.encipher_letters(&ciphertext)))
+  };
+
+  let mut plugs: Vec<(char, char)> = Vec::new();
+  let mut best = score(&plugs)?;
+  loop {
+    let mut improved = None;
+    for (i, c1) in ALPHABET.iter().enumerate() {
+      for c2 in &ALPHABET[i + 1..] {
+        let trial = toggle(&plugs, *c1, *c2);
+        if trial.len() > MAX_PLUGS {
+          continue;
+        }
+        let trial_score = score(&trial)?;
+        if trial_score > best {
+          (best, improved) = (trial_score, Some(trial));
+        }
+      }
+    }
+
+    match improved {
+      Some(trial) => plugs = trial,
+      None => break,
+    }
+  }
+
+  *machine.plugboard_mut() = plugboard(&plugs)?;
+  Ok((plugs, best))
+}
+
+fn toggle(plugs: &[(char, char)], c1: char, c2: char) -> Vec<(char, char)> {
+  // Take the cable C1-C2 out, or plug it in after unplugging C1 and C2
+  if plugs.contains(&(c1, c2)) {
+    return plugs.iter().filter(|pair| **pair != (c1, c2)).copied().collect();
+  }
+
+  plugs
+    .iter()
+    .filter(|(p1, p2)| ![c1, c2].contains(p1) && ![c1, c2].contains(p2))
+    .copied()
+    .chain([(c1, c2)])
+    .collect()
+}
+
+fn plugboard(plugs: &[(char, char)]) -> Result<Plugboard, EnigmaError> {
+  // Wire a plugboard with PLUGS
+  let mut plugboard = Plugboard::new();
+  for (c1, c2) in plugs {
+    plugboard.add_connection(*c1, *c2)?;
+  }
+  Ok(plugboard)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::catalogue::Catalogue;
+  use crate::loader;
+  use crate::search::Search;
+
+  const PLAINTEXT: &str = "WETTERBERICHTFUERDIEBISKAYAUNDDENKANALHEUTEFRUEHBEWOELKTSPAETERAUFKLARENDWINDAUSNORDWESTMITSTAERKEVIERBISFUENFINBOEENSECHSSICHTGUTZEITWEISEMAESSIGBEIREGENSCHAUERN";
+
+  fn machine() -> Machine {
+    let catalogue = Catalogue::from_yaml(&loader::permutations_yaml(&[]).unwrap());
+    let order = ["I".to_string(), "II".to_string(), "III".to_string()];
+    Search::new(&catalogue, Vec::new(), "B", &[]).machine(&order).unwrap()
+  }
+
+  fn encipher(rings: &[usize], positions: &[usize], plugs: &[(char, char)]) -> String {
+    let mut machine = machine();
+    machine.set_rings(rings);
+    machine.set_positions(positions);
+    *machine.plugboard_mut() = plugboard(plugs).unwrap();
+    machine.encipher_str(PLAINTEXT).unwrap()
+  }
+
+  #[test]
+  fn test_search_rings() {
+    // Found with the rings at 01 the fast rotor sits at its core position, H + 4 = L
+    let ciphertext = encipher(&[0, 0, 4], &[2, 3, 11], &[]);
+    let (rings, positions, ioc) = search_rings(&mut machine(), &ciphertext, &[2, 3, 7]).unwrap();
+    assert_eq!(rings[2], 4);
+    assert_eq!(positions[2], 11);
+    assert!(ioc > 0.06);
+  }
+
+  #[test]
+  fn test_climb_plugboard() {
+    let plugs = [('A', 'V'), ('B', 'S'), ('C', 'G'), ('D', 'L')];
+    let ciphertext = encipher(&[0, 0, 0], &[2, 3, 7], &plugs);
+    let model = NgramModel::from_corpus(include_str!("../assets/german.txt"), 3);
+
+    let mut machine = machine();
+    let (found, _) = climb_plugboard(&mut machine, &ciphertext, &[2, 3, 7], &model).unwrap();
+    machine.set_positions(&[2, 3, 7]);
+    assert_eq!(machine.encipher_str(&ciphertext).unwrap(), PLAINTEXT);
+    assert_eq!(found.len(), plugs.len());
+  }
+
+  #[test]
+  fn test_toggle() {
+    assert_eq!(toggle(&[('A', 'B')], 'A', 'B'), vec![]);
+    assert_eq!(toggle(&[('A', 'B'), ('C', 'D')], 'B', 'C'), vec![('B', 'C')]);
+    assert_eq!(toggle(&[('A', 'B')], 'C', 'D'), vec![('A', 'B'), ('C', 'D')]);
+  }
+}
@@ -0,0 +1,159 @@
+use std::fs;
+use std::path::Path;
+use yaml_rust::YamlLoader;
+
+use crate::catalogue::Catalogue;
+use crate::error::EnigmaError;
+use crate::machine::Machine;
+use crate::plugboard::{self, Plugboard};
+use crate::validation::{self, ValidationError};
+use crate::ALPHABET;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+  rotors: Vec<String>,
+  reflector: String,
+  rings: Vec<usize>,
+  positions: Vec<usize>,
+  plugs: Vec<(char, char)>,
+}
+
+impl Snapshot {
+
+  pub fn capture(machine: &Machine) -> Self {
+    // Capture the full setup and current rotor positions of the machine
+    Snapshot {
+      rotors: machine.rotors().iter().map(|rotor| rotor.get_name().to_string()).collect(),
+      reflector: machine.reflector().map(|reflector| reflector.get_name().to_string()).unwrap_or_default(),
+      rings: machine.rotors().iter().map(|rotor| rotor.get_ring()).collect(),
+      positions: machine.positions(),
+      plugs: machine.plugboard().get_pairs(),
+    }
+  }
+
+  pub fn restore(&self, catalogue: &Catalogue) -> Result<Machine, EnigmaError> {
+    // Rebuild the machine from the catalogue exactly as it was captured
+    let rotors = catalogue.wheel_order(&self.rotors)?;
+    let reflector = catalogue.reflector(&self.reflector, rotors.len() == 4)?;
+
+    let mut plugboard = Plugboard::new();
+    for (c1, c2) in &self.plugs {
+      plugboard.add_connection(*c1, *c2)?;
+    }
+
+    let mut machine = Machine::new(rotors, Some(reflector), plugboard);
+    machine.set_rings(&self.rings);
+    machine.set_positions(&self.positions);
+    Ok(machine)
+  }
+
+  pub fn to_yaml(&self) -> String {
+    // Serialize the snapshot using the same notation as the command line options
+    let rings: Vec<String> = self.rings.iter().map(|ring| format!("{:02}", ring + 1)).collect();
+    let positions: String = self.positions.iter().map(|position| ALPHABET[*position]).collect();
+    let plugs: Vec<String> = self.plugs.iter().map(|(c1, c2)| format!("{}{}", c1, c2)).collect();
+
+    format!(
+      "rotors: [{}]\nreflector: \"{}\"\nrings: \"{}\"\npositions: \"{}\"\nplugboard: \"{}\"\n",
+      self.rotors.join(", "),
+      self.reflector,
+      rings.join(" "),
+      positions,
+      plugs.join(" "),
+    )
+  }
+
+  pub fn describe(&self) -> String {
+    // Summarise the settings on one line, e.g. for a message log
+    let rings: Vec<String> = self.rings.iter().map(|ring| format!("{:02}", ring + 1)).collect();
+    let positions: String = self.positions.iter().map(|position| ALPHABET[*position]).collect();
+    let plugs: Vec<String> = self.plugs.iter().map(|(c1, c2)| format!("{}{}", c1, c2)).collect();
+
+    format!(
+      "Rotors {} | Reflector {} | Rings {} | Positions {} | Plugs {}",
+      self.rotors.join(" "),
+      self.reflector,
+      rings.join(" "),
+      positions,
+      if plugs.is_empty() { String::from("-") } else { plugs.join(" ") },
+    )
+  }
+
+  pub fn from_yaml_str(yaml_str: &str) -> Result<Self, EnigmaError> {
+    // Parse a snapshot written by `to_yaml`
+    let docs = YamlLoader::load_from_str(yaml_str)?;
+    let yaml = docs.into_iter().next().ok_or_else(|| ValidationError::EmptyFile("state".to_string()))?;
+    let field = |name: &str| yaml[name].as_str().ok_or_else(|| ValidationError::MissingField(name.to_string()));
+
+    let rotors: Vec<String> = yaml["rotors"]
+      .as_vec()
+      .ok_or_else(|| ValidationError::MissingField("rotors".to_string()))?
+      .iter()
+      .filter_map(|name| name.as_str().map(String::from))
+      .collect();
+
+    Ok(Snapshot {
+      reflector: field("reflector")?.to_string(),
+      rings: validation::parse_settings(field("rings")?, "rings", rotors.len())?,
+      positions: validation::parse_settings(field("positions")?, "positions", rotors.len())?,
+      plugs: plugboard::parse_plugs(yaml["plugboard"].as_str().unwrap_or(""))?,
+      rotors,
+    })
+  }
+
+  pub fn save(&self, path: &Path) -> Result<(), EnigmaError> {
+    // Write the snapshot to a file
+    fs::write(path, self.to_yaml())?;
+    Ok(())
+  }
+
+  pub fn load(path: &Path) -> Result<Self, EnigmaError> {
+    // Read a snapshot from a file
+    Self::from_yaml_str(&fs::read_to_string(path)?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn catalogue() -> Catalogue {
+    let yaml = YamlLoader::load_from_str("
+rotors:
+  I:   { wiring: EKMFLGDQVZNTOWYHXUSPAIBRCJ, notches: Q }
+  II:  { wiring: AJDKSIRUXBLHWTMCQGZNPYFVOE, notches: E }
+  IV:  { wiring: ESOVPZJAYQUIRHXLNFTGKDCMWB, notches: J }
+reflectors:
+  B:   { wiring: YRUHQSLDPXNGOKMIEBFZCWVJAT }
+").unwrap();
+    Catalogue::from_yaml(&yaml[0])
+  }
+
+  #[test]
+  fn test_snapshot_round_trip() {
+    let catalogue = catalogue();
+    let mut machine = catalogue.wheel_order(&["II".to_string(), "IV".to_string(), "I".to_string()])
+      .map(|rotors| Machine::new(rotors, Some(catalogue.reflector("B", false).unwrap()), Plugboard::new()))
+      .unwrap();
+    machine.set_rings(&[0, 11, 21]);
+    machine.set_positions(&[16, 4, 21]);
+    machine.plugboard_mut().add_connection('A', 'V').unwrap();
+    machine.plugboard_mut().add_connection('B', 'S').unwrap();
+    machine.encipher_str("PAUSEMIDMESSAGE").unwrap();
+
+    let snapshot = Snapshot::capture(&machine);
+    let loaded = Snapshot::from_yaml_str(&snapshot.to_yaml()).unwrap();
+    assert_eq!(loaded, snapshot);
+
+    // The restored machine carries on exactly where the original stopped
+    let mut restored = loaded.restore(&catalogue).unwrap();
+    assert_eq!(restored.window(), machine.window());
+    assert_eq!(restored.encipher_str("RESUME").unwrap(), machine.encipher_str("RESUME").unwrap());
+  }
+
+  #[test]
+  fn test_invalid_snapshot() {
+    assert!(Snapshot::from_yaml_str("rotors: [I, II, IV]\nreflector: B\nrings: AA\npositions: AAA").is_err());
+    assert!(Snapshot::from_yaml_str("rotors: [I, II, IV]\nreflector: B\npositions: AAA").is_err());
+  }
+}
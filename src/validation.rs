@@ -3,20 +3,72 @@ use std::error::Error;
 use std::fmt;
 use yaml_rust::Yaml;
 
+use crate::plugboard;
+
 // Custom error type for validation errors
 #[derive(Debug)]
 pub enum ValidationError {
+    EmptyFile(String),
     MissingField(String),
     InvalidPermutation(String, String),
+    InvalidNotches(String, String),
+    InvalidSetting(String, String),
+    InvalidReflector(String, String),
+    UnknownRotor(String),
+    UnknownReflector(String),
+    DuplicateRotor(String),
+    InvalidWheelOrder(String),
+    InvalidM4WheelOrder(String),
+    IncompatibleReflector(String),
+    InvalidPlugs(String),
+    UnknownDay(u32),
+    InvalidMonth(String),
+    TooFewWheelOrders(String),
+    InvalidRadioMessage(String),
+    InvalidLayout(String),
+    InvalidCrib(String, String),
 }
 
 impl fmt::Display for ValidationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            ValidationError::EmptyFile(path) => write!(f, "Empty YAML file: {}", path),
             ValidationError::MissingField(field) => write!(f, "Missing required field: {}", field),
             ValidationError::InvalidPermutation(field, value) => {
                 write!(f, "Invalid permutation in {}: '{}' - must contain exactly all letters A-Z once", field, value)
             }
+            ValidationError::InvalidNotches(field, value) => {
+                write!(f, "Invalid notches in {}: '{}' - must be distinct letters A-Z", field, value)
+            }
+            ValidationError::InvalidSetting(field, value) => {
+                write!(f, "Invalid {}: '{}' - expected one letter (A-Z) or number (01-26) per rotor", field, value)
+            }
+            ValidationError::InvalidReflector(field, value) => {
+                write!(f, "Invalid reflector in {}: '{}' - must swap letters in pairs with none mapping to itself", field, value)
+            }
+            ValidationError::UnknownRotor(name) => write!(f, "Unknown rotor: {}", name),
+            ValidationError::UnknownReflector(name) => write!(f, "Unknown reflector: {}", name),
+            ValidationError::DuplicateRotor(name) => write!(f, "Rotor {} is used more than once in the wheel order", name),
+            ValidationError::IncompatibleReflector(name) => {
+                write!(f, "Reflector {} does not fit this machine - the M4 needs a thin reflector, the three rotor machine a thick one", name)
+            }
+            ValidationError::InvalidPlugs(plugs) => {
+                write!(f, "Invalid plugboard connections: '{}' - expected up to 10 pairs of distinct letters, e.g. \"AV BS CG\"", plugs)
+            }
+            ValidationError::UnknownDay(day) => write!(f, "The key sheet has no key for day {}", day),
+            ValidationError::InvalidMonth(month) => write!(f, "Invalid month: '{}' - expected YYYY-MM, e.g. 2026-11", month),
+            ValidationError::InvalidRadioMessage(reason) => write!(f, "Invalid radio message: {}", reason),
+            ValidationError::InvalidLayout(reason) => write!(f, "Invalid screen layout: {}", reason),
+            ValidationError::InvalidCrib(crib, reason) => write!(f, "Invalid crib '{}': {}", crib, reason),
+            ValidationError::TooFewWheelOrders(rotors) => {
+                write!(f, "Not enough rotors in '{}' for a different wheel order every day of the month", rotors)
+            }
+            ValidationError::InvalidWheelOrder(order) => {
+                write!(f, "Invalid wheel order: '{}' - expected three rotors, or a thin Greek wheel followed by three rotors (M4)", order)
+            }
+            ValidationError::InvalidM4WheelOrder(order) => {
+                write!(f, "Invalid wheel order for --m4: '{}' - the M4 needs a thin Greek wheel followed by three rotors", order)
+            }
         }
     }
 }
@@ -24,7 +76,7 @@ impl fmt::Display for ValidationError {
 impl Error for ValidationError {}
 
 // Function to validate a single permutation string
-fn validate_permutation(perm: &str, field_name: &str) -> Result<(), ValidationError> {
+pub fn validate_permutation(perm: &str, field_name: &str) -> Result<(), ValidationError> {
     // Check if the string contains exactly 26 characters
     if perm.len() != 26 {
         return Err(ValidationError::InvalidPermutation(
@@ -46,36 +98,140 @@ fn validate_permutation(perm: &str, field_name: &str) -> Result<(), ValidationEr
     Ok(())
 }
 
+// Function to validate a string of notch letters
+fn validate_notches(notches: &str, field_name: &str) -> Result<(), ValidationError> {
+    let unique_chars: HashSet<char> = notches.chars().collect();
+    if unique_chars.len() != notches.len() || !notches.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(ValidationError::InvalidNotches(
+            field_name.to_string(),
+            notches.to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+// Function to validate that a permutation is a reflector (paired swaps, no fixed points)
+fn validate_reflector(perm: &str, field_name: &str) -> Result<(), ValidationError> {
+    validate_permutation(perm, field_name)?;
+
+    let letters: Vec<char> = perm.chars().collect();
+    for (i, c) in letters.iter().enumerate() {
+        let j = *c as usize - 'A' as usize;
+        if i == j || letters[j] as usize - 'A' as usize != i {
+            return Err(ValidationError::InvalidReflector(
+                field_name.to_string(),
+                perm.to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Parse rotor settings given as letters ("AQV") or separated numbers ("01 17 22")
+pub fn parse_settings(value: &str, field_name: &str, expected: usize) -> Result<Vec<usize>, ValidationError> {
+    let invalid = || ValidationError::InvalidSetting(field_name.to_string(), value.to_string());
+
+    let settings: Vec<usize> = if value.chars().any(|c| c.is_ascii_digit()) {
+        value
+            .split(|c: char| c.is_whitespace() || c == ',' || c == '-')
+            .filter(|s| !s.is_empty())
+            .map(|s| match s.parse::<usize>() {
+                Ok(n) if (1..=26).contains(&n) => Ok(n - 1),
+                _ => Err(invalid()),
+            })
+            .collect::<Result<_, _>>()?
+    } else {
+        value
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != ',' && *c != '-')
+            .map(|c| match c.to_ascii_uppercase() {
+                c if c.is_ascii_uppercase() => Ok(c as usize - 'A' as usize),
+                _ => Err(invalid()),
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    if settings.len() != expected {
+        return Err(invalid());
+    }
+
+    Ok(settings)
+}
+
 // Main validation function
-pub fn validate_yaml(yaml: &Yaml) -> Result<(), Box<dyn Error>> {
-    // Validate rotor field exists and is a sequence
-    let rotors = yaml["rotor"].as_vec()
-        .ok_or_else(|| ValidationError::MissingField("rotor".to_string()))?;
+pub fn validate_yaml(yaml: &Yaml) -> Result<(), ValidationError> {
+    // Validate rotor catalogue exists and is a mapping
+    let rotors = yaml["rotors"].as_hash()
+        .ok_or_else(|| ValidationError::MissingField("rotors".to_string()))?;
     
-    // Validate each rotor permutation
-    for (i, rotor) in rotors.iter().enumerate() {
-        let rotor_str = rotor.as_str()
-            .ok_or_else(|| ValidationError::InvalidPermutation(
-                format!("rotor[{}]", i),
-                format!("{:?}", rotor),
-            ))?;
-            
-        // Extract only the characters before any comment
-        let rotor_str = if let Some(comment_pos) = rotor_str.find('#') {
-            rotor_str[..comment_pos].trim()
-        } else {
-            rotor_str.trim()
-        };
-        
-        validate_permutation(rotor_str, &format!("rotor[{}]", i))?;
+    // Validate each rotor permutation and its notches
+    for (name, rotor) in rotors.iter() {
+        let field_name = format!("rotors.{}", name.as_str().unwrap_or("?"));
+
+        let rotor_str = rotor["wiring"].as_str()
+            .ok_or_else(|| ValidationError::MissingField(format!("{}.wiring", field_name)))?;
+        validate_permutation(rotor_str, &field_name)?;
+
+        if let Some(notches) = rotor["notches"].as_str() {
+            validate_notches(notches, &field_name)?;
+        }
     }
     
-    // Validate reflector field if it exists
-    if let Some(reflector) = yaml["reflector"].as_str() {
-      validate_permutation(reflector, "reflector")?;
+    // Validate reflector catalogue exists and is a mapping
+    let reflectors = yaml["reflectors"].as_hash()
+        .ok_or_else(|| ValidationError::MissingField("reflectors".to_string()))?;
+
+    // Validate each reflector permutation
+    for (name, reflector) in reflectors.iter() {
+        let field_name = format!("reflectors.{}", name.as_str().unwrap_or("?"));
+
+        let reflector_str = reflector["wiring"].as_str()
+            .ok_or_else(|| ValidationError::MissingField(format!("{}.wiring", field_name)))?;
+        validate_reflector(reflector_str, &field_name)?;
+    }
+
+    // Validate the default plugboard connections, if given
+    for plugboard in [&yaml["plugboard"], &yaml["m4"]["plugboard"]] {
+        if let Some(plugs) = plugboard.as_str() {
+            plugboard::parse_plugs(plugs)?;
+        }
     }
     
     Ok(())
     
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings() {
+        assert_eq!(parse_settings("AQV", "rings", 3).unwrap(), vec![0, 16, 21]);
+        assert_eq!(parse_settings("a q v", "rings", 3).unwrap(), vec![0, 16, 21]);
+        assert_eq!(parse_settings("01 17 22", "rings", 3).unwrap(), vec![0, 16, 21]);
+        assert_eq!(parse_settings("1,17,22", "rings", 3).unwrap(), vec![0, 16, 21]);
+        assert!(parse_settings("AQ", "rings", 3).is_err());
+        assert!(parse_settings("01 27 22", "rings", 3).is_err());
+        assert!(parse_settings("A?V", "rings", 3).is_err());
+    }
+
+    #[test]
+    fn test_validate_reflector() {
+        assert!(validate_reflector("YRUHQSLDPXNGOKMIEBFZCWVJAT", "B").is_ok());
+        assert!(validate_reflector("EKMFLGDQVZNTOWYHXUSPAIBRCJ", "I").is_err());
+        assert!(validate_reflector("ABCDEFGHIJKLMNOPQRSTUVWXYZ", "identity").is_err());
+    }
+
+    #[test]
+    fn test_validate_yaml_plugboard() {
+        let yaml = |plugboard: &str| yaml_rust::YamlLoader::load_from_str(&format!(
+            "rotors: {{}}\nreflectors: {{}}\nplugboard: \"{}\"", plugboard
+        )).unwrap().remove(0);
+        assert!(validate_yaml(&yaml("AV BS CG DL FU HZ IN KM OW RX")).is_ok());
+        assert!(validate_yaml(&yaml("AV BA")).is_err());
+        assert!(validate_yaml(&yaml("AB CD EF GH IJ KL MN OP QR ST UV")).is_err());
+    }
+}
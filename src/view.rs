@@ -1,6 +1,7 @@
 use std::io;
 use std::io::Write;
 use std::collections::HashMap;
+use std::ops::Range;
 use std::time::Duration;
 use std::thread::sleep;
 
@@ -11,12 +12,17 @@ use crossterm::{
   cursor::{self},
 };
 
+use crate::layout::Layout;
 use crate::INSTRUCTIONS;
 
+pub type Frame = Vec<Vec<(char, Option<Color>)>>;
+
 pub struct EnigmaView {
-  frame: Vec<Vec<(char, Option<Color>)>>,
+  frame: Frame,
   ascii_mapping_top: HashMap<char, (usize, usize)>,
   ascii_mapping_plugboard: HashMap<char, (usize, usize)>,
+  top_rows: Range<usize>,
+  front_rows: Range<usize>,
   previous_key_press: Option<char>,
   previous_lamp: Option<char>,
   previous_wire: Option<char>,
@@ -25,18 +31,20 @@ pub struct EnigmaView {
   lamp_color: Color,
   front_view: bool,
   message_buffer: String,
+  status: String,
 }
 
 impl EnigmaView {
   pub fn new(
-    frame: Vec<Vec<(char, Option<Color>)>>,
-    ascii_mapping_top: HashMap<char, (usize, usize)>,
-    ascii_mapping_plugboard: HashMap<char, (usize, usize)>, 
+    frame: Frame,
+    layout: Layout,
   ) -> Self {
     EnigmaView { 
       frame,
-      ascii_mapping_top,
-      ascii_mapping_plugboard,
+      ascii_mapping_top: layout.top().clone(),
+      ascii_mapping_plugboard: layout.plugboard().clone(),
+      top_rows: layout.top_view(),
+      front_rows: layout.front_view(),
       previous_key_press: None,
       previous_lamp: None,
       previous_wire: None,
@@ -45,25 +53,27 @@ impl EnigmaView {
       lamp_color: Color::Yellow,
       front_view: false,
       message_buffer: String::new(),
+      status: String::new(),
              }
   }
 
-  pub fn start(&mut self, perspective: &str) {
+  pub fn start(&mut self, perspective: &str) -> io::Result<()> {
     // Start the view for the chosen perspective
-    terminal::enable_raw_mode().unwrap();
+    terminal::enable_raw_mode()?;
     
     match perspective {
       "top" => self.front_view = false,
       "front" => self.front_view = true,
       _ => {}
     }
+    Ok(())
   }
   
-  pub fn end(&mut self) {
-    // End view for the current perspective
-    execute!(self.stdout, terminal::Clear(ClearType::All)).unwrap();
-    execute!(self.stdout, cursor::MoveTo(0, 0)).unwrap();
-    terminal::disable_raw_mode().unwrap();
+  pub fn end(&mut self) -> io::Result<()> {
+    // End view for the current perspective, raw mode is always disabled
+    let cleared = execute!(self.stdout, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0));
+    terminal::disable_raw_mode()?;
+    cleared
   }
 
   pub fn add_initial_plug(&mut self, c: char) {
@@ -77,20 +87,9 @@ impl EnigmaView {
 
   pub fn add_final_plug(&mut self, c: char, num_connection: usize) {
     // Complete the connection by labelling initial and final plugs
-    let num_char = char::from_digit(num_connection as u32, 10).unwrap();
-
-    // Final plug
-    if let Some((x, y)) = self.ascii_mapping_plugboard.get(&c) {
-      self.frame[*x][*y].0 = num_char;
-      self.frame[*x][*y].1 = Some(Color::DarkGrey);
-    }
-
-    // Initial plug
+    self.label_plug(c, num_connection);
     if let Some(pc) = self.previous_wire.take() {
-      if let Some((px, py)) = self.ascii_mapping_plugboard.get(&pc) {
-        self.frame[*px][*py].0 = num_char;
-        self.frame[*px][*py].1 = Some(Color::DarkGrey);
-      }
+      self.label_plug(pc, num_connection);
     }
   }
 
@@ -100,13 +99,33 @@ impl EnigmaView {
       self.frame[*x][*y].0 = ':';
       self.frame[*x][*y].1 = None;
     }
+    if self.previous_wire == Some(c) {
+      self.previous_wire = None;
+    }
+  }
+
+  pub fn show_plugs(&mut self, pairs: &[(char, char)]) {
+    // Label every connected pair with its number, so numbering stays in order after an unplug
+    for (num_connection, (c1, c2)) in pairs.iter().enumerate() {
+      self.label_plug(*c1, num_connection);
+      self.label_plug(*c2, num_connection);
+    }
+  }
+
+  fn label_plug(&mut self, c: char, num_connection: usize) {
+    // Label the plug at character C with the connection number
+    let num_char = char::from_digit(num_connection as u32, 10).unwrap();
+    if let Some((x, y)) = self.ascii_mapping_plugboard.get(&c) {
+      self.frame[*x][*y].0 = num_char;
+      self.frame[*x][*y].1 = Some(Color::DarkGrey);
+    }
   }
   
-  pub fn flip(&mut self, ins: bool) {
+  pub fn flip(&mut self, ins: bool) -> io::Result<()> {
     // Clear screen, move cursor to top-left, print new frame
-    execute!(self.stdout, terminal::Clear(ClearType::All)).unwrap();
-    execute!(self.stdout, cursor::MoveTo(0, 0)).unwrap();
-    self.print_colored_frame(ins);
+    execute!(self.stdout, terminal::Clear(ClearType::All))?;
+    execute!(self.stdout, cursor::MoveTo(0, 0))?;
+    self.print_colored_frame(ins)
   }
 
   pub fn update_keyboard(&mut self, c: char) {
@@ -134,6 +153,22 @@ impl EnigmaView {
 
   }
 
+  pub fn clear_keyboard(&mut self) {
+    // Remove the highlighting of the last key press and lamp
+    for previous in [self.previous_key_press.take(), self.previous_lamp.take()].into_iter().flatten() {
+      if let Some((x, y)) = self.ascii_mapping_top.get(&previous) {
+        self.frame[*x][*y].1 = None;
+      }
+    }
+  }
+
+  pub fn set_rotor(&mut self, rotor_c: char, c: char) {
+    // Set the letter shown in the corresponding rotor window without animation
+    if let Some((y, x)) = self.ascii_mapping_top.get(&rotor_c) {
+      self.frame[*y][*x].0 = c;
+    }
+  }
+
   pub fn rotate_rotor_fast(&mut self, rotor_c: char, next_c: char) {
     // Update the corresponding rotor display with roll animation
     let (y, x) = self.ascii_mapping_top[&rotor_c];
@@ -173,7 +208,12 @@ impl EnigmaView {
     self.message_buffer.clear();
   }
 
-  fn print_colored_frame(&mut self, ins: bool) {
+  pub fn update_status(&mut self, status: &str) {
+    // Update the status line below the machine
+    self.status = String::from(status);
+  }
+
+  fn print_colored_frame(&mut self, ins: bool) -> io::Result<()> {
     // Print the current frame with colour highlighting
 
     // Create output buffer
@@ -181,11 +221,12 @@ impl EnigmaView {
 
     // Add instructions to the top
     if ins {
-      output_buffer.push_str(if self.front_view { &INSTRUCTIONS[0] } else { &INSTRUCTIONS[1] });
+      output_buffer.push_str(if self.front_view { INSTRUCTIONS[0] } else { INSTRUCTIONS[1] });
     }
     
     // Iterate over the frame and apply color if necessary
-    let frame = if self.front_view { &self.frame[16..] } else { &self.frame[..17] };
+    let rows = if self.front_view { self.front_rows.clone() } else { self.top_rows.clone() };
+    let frame = &self.frame[rows];
     for row in frame {
       for &c in row {
         match c.1 {
@@ -202,13 +243,17 @@ impl EnigmaView {
       output_buffer.push_str("\r\n");
     }
 
+    // Add the status line below the machine
+    output_buffer.push_str(&self.status);
+    output_buffer.push_str("\r\n");
+
     // Add the message at the bottom
     output_buffer.push_str("\r\n");
     output_buffer.push_str(&self.message_buffer);
 
     // Write everything at once
-    write!(self.stdout, "{}", output_buffer).unwrap();
-    self.stdout.flush().unwrap();
+    write!(self.stdout, "{}", output_buffer)?;
+    self.stdout.flush()
 
   }
 }
\ No newline at end of file